@@ -5,6 +5,8 @@ use crate::consts::{Direction, PieceColor, PieceType, Sq};
 use crate::magics::{BISHOP_MAGICS, ROOK_MAGICS};
 use crate::{COL, ROW, SQ};
 
+use std::sync::LazyLock;
+
 // Total number of square a bishop can go to from a certain square
 #[rustfmt::skip]
 pub const BISHOP_RELEVANT_BITS: [u32; 64] = [
@@ -31,97 +33,158 @@ pub const ROOK_RELEVANT_BITS: [u32; 64] = [
     12, 11, 11, 11, 11, 11, 11, 12,
 ];
 
-#[derive(Clone)]
-pub struct AttackInfo {
-    pub pawn: [[BB; 64]; 2],
-    pub knight: [BB; 64],
-    pub king: [BB; 64],
-    pub bishop_occ_mask: [BB; 64],
-    pub bishop_attack: Vec<Vec<BB>>,
-    pub rook_occ_mask: [BB; 64],
-    pub rook_attack: Vec<Vec<BB>>,
+// Magic bitboard lookup for one slider on one square
+#[derive(Clone, Copy, Default)]
+struct Magic {
+    occ_mask: BB,
+    magic: u64,
+    shift: u32,
+    // Index of the square's first entry in `AttackTables::slider`
+    offset: usize,
 }
 
-impl AttackInfo {
-    pub fn new() -> Self {
-        Self {
+impl Magic {
+    fn index(&self, blocker_board: BB) -> usize {
+        self.offset + ((blocker_board & self.occ_mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+// 64 squares of bishop (5248 entries) and rook (102400 entries) attacks, back to back
+const SLIDER_TABLE_SIZE: usize = 107648;
+
+struct AttackTables {
+    pawn: [[BB; 64]; 2],
+    knight: [BB; 64],
+    king: [BB; 64],
+    bishop: [Magic; 64],
+    rook: [Magic; 64],
+    slider: Vec<BB>,
+    between: Vec<BB>,
+}
+
+// Built on first use and then shared (read-only) by every board and search thread
+static TABLES: LazyLock<AttackTables> = LazyLock::new(AttackTables::new);
+
+impl AttackTables {
+    fn new() -> Self {
+        let mut tables = Self {
             pawn: [[0; 64]; 2],
             knight: [0; 64],
             king: [0; 64],
-            bishop_occ_mask: [0; 64],
-            bishop_attack: vec![vec![0; 512]; 64],
-            rook_occ_mask: [0; 64],
-            rook_attack: vec![vec![0; 4096]; 64],
-        }
+            bishop: [Magic::default(); 64],
+            rook: [Magic::default(); 64],
+            slider: vec![0; SLIDER_TABLE_SIZE],
+            between: vec![0; 64 * 64],
+        };
+        gen_leapers(&mut tables);
+        let offset = gen_sliding(&mut tables, PieceType::Bishop, 0);
+        let offset = gen_sliding(&mut tables, PieceType::Rook, offset);
+        debug_assert_eq!(offset, SLIDER_TABLE_SIZE);
+        gen_between(&mut tables);
+        tables
     }
+}
 
-    pub fn init(&mut self) {
-        gen_leapers(self);
-        gen_sliding(self, PieceType::Bishop);
-        gen_sliding(self, PieceType::Rook);
-    }
+/// Builds the attack tables up front, so the first search doesn't pay for it.
+/// Calling this is optional; the tables are also built on first use.
+pub fn init() {
+    LazyLock::force(&TABLES);
+}
 
-    pub fn get_bishop_attack(&self, sq: Sq, blocker_board: BB) -> BB {
-        let mut blocker = blocker_board;
-        blocker &= self.bishop_occ_mask[sq as usize];
-        let num = u128::from(blocker) * u128::from(BISHOP_MAGICS[sq as usize]);
-        blocker = (num as u64) >> (64 - BISHOP_RELEVANT_BITS[sq as usize]);
-        self.bishop_attack[sq as usize][blocker as usize]
-    }
+/// Squares attacked by a pawn of `color` standing on `sq`
+pub fn pawn_attacks(color: PieceColor, sq: usize) -> BB {
+    TABLES.pawn[color as usize][sq]
+}
 
-    pub fn get_rook_attack(&self, sq: Sq, blocker_board: BB) -> BB {
-        let mut blocker = blocker_board;
-        blocker &= self.rook_occ_mask[sq as usize];
-        let num = u128::from(blocker) * u128::from(ROOK_MAGICS[sq as usize]);
-        blocker = (num as u64) >> (64 - ROOK_RELEVANT_BITS[sq as usize]);
-        self.rook_attack[sq as usize][blocker as usize]
-    }
+pub fn knight_attacks(sq: usize) -> BB {
+    TABLES.knight[sq]
+}
 
-    pub fn get_queen_attack(&self, sq: Sq, blocker_board: BB) -> BB {
-        self.get_bishop_attack(sq, blocker_board) | self.get_rook_attack(sq, blocker_board)
-    }
+pub fn king_attacks(sq: usize) -> BB {
+    TABLES.king[sq]
+}
+
+pub fn bishop_attacks(sq: Sq, blocker_board: BB) -> BB {
+    let tables = &*TABLES;
+    tables.slider[tables.bishop[sq as usize].index(blocker_board)]
+}
+
+pub fn rook_attacks(sq: Sq, blocker_board: BB) -> BB {
+    let tables = &*TABLES;
+    tables.slider[tables.rook[sq as usize].index(blocker_board)]
+}
+
+pub fn queen_attacks(sq: Sq, blocker_board: BB) -> BB {
+    bishop_attacks(sq, blocker_board) | rook_attacks(sq, blocker_board)
 }
 
-fn gen_leapers(attack_info: &mut AttackInfo) {
+/// Squares strictly between two squares on the same rank, file or diagonal; empty otherwise
+pub fn between(sq1: usize, sq2: usize) -> BB {
+    TABLES.between[sq1 * 64 + sq2]
+}
+
+fn gen_leapers(tables: &mut AttackTables) {
     for sq in 0..64 {
-        gen_pawn(attack_info, sq, PieceColor::Light);
-        gen_pawn(attack_info, sq, PieceColor::Dark);
-        gen_knight(attack_info, sq);
-        gen_king(attack_info, sq);
+        gen_pawn(tables, sq, PieceColor::Light);
+        gen_pawn(tables, sq, PieceColor::Dark);
+        gen_knight(tables, sq);
+        gen_king(tables, sq);
     }
 }
-fn gen_sliding(attack_info: &mut AttackInfo, piece: PieceType) {
-    for sq in 0..64 {
-        attack_info.bishop_occ_mask[sq] = gen_bishop_occ(sq);
-        attack_info.rook_occ_mask[sq] = gen_rook_occ(sq);
-
-        let curr_mask = if piece == PieceType::Bishop {
-            attack_info.bishop_occ_mask[sq]
 
+// Fills in the slider's attacks starting at `offset` and returns the offset after the last entry
+fn gen_sliding(tables: &mut AttackTables, piece: PieceType, mut offset: usize) -> usize {
+    for sq in 0..64 {
+        let (occ_mask, magic, bit_count) = if piece == PieceType::Bishop {
+            (gen_bishop_occ(sq), BISHOP_MAGICS[sq], BISHOP_RELEVANT_BITS[sq])
         } else {
-            attack_info.rook_occ_mask[sq]
+            (gen_rook_occ(sq), ROOK_MAGICS[sq], ROOK_RELEVANT_BITS[sq])
+        };
+        let entry = Magic {
+            occ_mask,
+            magic,
+            shift: 64 - bit_count,
+            offset,
         };
 
-        let bit_count = curr_mask.count_ones();
         for count in 0..(1 << bit_count) {
-            let occupancy = set_occ(count, bit_count, curr_mask);
-            let magic_ind;
-            if piece == PieceType::Bishop {
-                let num = u128::from(occupancy) * u128::from(BISHOP_MAGICS[sq]);
-                magic_ind = (num as u64) >> (64 - bit_count);
-                attack_info.bishop_attack[sq][magic_ind as usize] =
-                    gen_bishop_attack(sq, occupancy);
+            let occupancy = set_occ(count, bit_count, occ_mask);
+            tables.slider[entry.index(occupancy)] = if piece == PieceType::Bishop {
+                gen_bishop_attack(sq, occupancy)
+            } else {
+                gen_rook_attack(sq, occupancy)
+            };
+        }
+        if piece == PieceType::Bishop {
+            tables.bishop[sq] = entry;
+        } else {
+            tables.rook[sq] = entry;
+        }
+        offset += 1 << bit_count;
+    }
+    offset
+}
+
+fn gen_between(tables: &mut AttackTables) {
+    for sq1 in 0..64 {
+        for sq2 in 0..64 {
+            let (r1, f1) = (ROW!(sq1) as i32, COL!(sq1) as i32);
+            let (r2, f2) = (ROW!(sq2) as i32, COL!(sq2) as i32);
+            tables.between[sq1 * 64 + sq2] = if sq1 == sq2 {
+                0
+            } else if r1 == r2 || f1 == f2 {
+                gen_rook_attack(sq1, 1 << sq2) & gen_rook_attack(sq2, 1 << sq1)
+            } else if (r1 - r2).abs() == (f1 - f2).abs() {
+                gen_bishop_attack(sq1, 1 << sq2) & gen_bishop_attack(sq2, 1 << sq1)
             } else {
-                let num = u128::from(occupancy) * u128::from(ROOK_MAGICS[sq]);
-                magic_ind = (num as u64) >> (64 - bit_count);
-                attack_info.rook_attack[sq][magic_ind as usize] = gen_rook_attack(sq, occupancy);
-            }
+                0
+            };
         }
     }
 }
 
-fn gen_pawn(attack_info: &mut AttackInfo, sq: usize, color: PieceColor) {
-    let bb = &mut attack_info.pawn[color as usize][sq];
+fn gen_pawn(tables: &mut AttackTables, sq: usize, color: PieceColor) {
+    let bb = &mut tables.pawn[color as usize][sq];
     if color == PieceColor::Light {
         if ROW!(sq) > 0 && COL!(sq) > 0 {
             bb.set((sq as i32 + Direction::SW as i32) as usize);
@@ -139,8 +202,8 @@ fn gen_pawn(attack_info: &mut AttackInfo, sq: usize, color: PieceColor) {
     }
 }
 
-fn gen_knight(attack_info: &mut AttackInfo, sq: usize) {
-    let bb = &mut attack_info.knight[sq];
+fn gen_knight(tables: &mut AttackTables, sq: usize) {
+    let bb = &mut tables.knight[sq];
     if ROW!(sq) <= 5 && COL!(sq) >= 1 {
         bb.set((sq as i32 + Direction::NW_N as i32) as usize);
     }
@@ -174,8 +237,8 @@ fn gen_knight(attack_info: &mut AttackInfo, sq: usize) {
     }
 }
 
-fn gen_king(attack_info: &mut AttackInfo, sq: usize) {
-    let bb = &mut attack_info.king[sq];
+fn gen_king(tables: &mut AttackTables, sq: usize) {
+    let bb = &mut tables.king[sq];
     if ROW!(sq) > 0 {
         bb.set((sq as i32 + Direction::South as i32) as usize);
     }
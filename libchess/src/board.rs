@@ -1,15 +1,24 @@
-use crate::attack::AttackInfo;
+use crate::attack;
 use crate::bb::{BBUtil, BB};
 use crate::consts::{Piece, PieceColor, Sq};
-use crate::{fen, zobrist};
-use crate::zobrist::{ZobristAction, ZobristInfo};
+use crate::moves::{Move, MoveUtil};
+use crate::fen::{self, FenError};
+use crate::san::{self, SanError};
+use crate::zobrist;
+use crate::zobrist::ZobristAction;
 use crate::SQ;
 
-#[derive(Clone)]
+#[derive(Copy, Clone)]
 pub struct Position {
     pub piece: [BB; 12],
 }
 
+impl Default for Position {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Position {
     pub fn new() -> Self {
         Position { piece: [0; 12] }
@@ -38,7 +47,7 @@ impl Position {
     }
 }
 
-#[derive(Clone)]
+#[derive(Copy, Clone)]
 pub struct State {
     pub side: PieceColor,
     pub xside: PieceColor,
@@ -64,6 +73,12 @@ pub enum CastlingType {
     BlackQueenside,
 }
 
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl State {
     pub fn new() -> Self {
         State {
@@ -99,29 +114,64 @@ impl State {
     }
 }
 
-#[derive(Clone)]
+// Everything that can't be recovered from a move alone once it has been played
+#[derive(Copy, Clone)]
+pub struct Undo {
+    pub mv: Move,
+    pub captured: Option<Piece>,
+    pub castling: u8,
+    pub enpassant: Sq,
+    pub half_moves: u32,
+    pub key: u64,
+    pub lock: u64,
+}
+
+#[derive(Copy, Clone)]
 pub struct Board {
     pub pos: Position,
     pub state: State,
-    pub zobrist_info: ZobristInfo,
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Board {
     pub fn new() -> Self {
-        let mut this = Board {
+        Board {
             pos: Position::new(),
             state: State::new(),
-            zobrist_info: ZobristInfo::new(),
-        };
-        this.zobrist_info.init();
-        this
+        }
+    }
+
+    pub fn from_fen(fen: &str) -> Result<Board, FenError> {
+        let mut board = Board::new();
+        fen::parse(fen, &mut board)?;
+        fen::validate(&board)?;
+        Ok(board)
+    }
+
+    // The board is left untouched if the FEN is invalid
+    pub fn set_fen(&mut self, fen: &str) -> Result<(), FenError> {
+        *self = Board::from_fen(fen)?;
+        Ok(())
+    }
+
+    pub fn to_fen(&self) -> String {
+        fen::serialize(self)
     }
 
-    pub fn set_fen(&mut self, fen: &str) {
-        fen::parse(fen, self);
+    pub fn move_to_san(&self, mv: Move) -> String {
+        san::to_san(self, mv)
     }
 
-    pub fn add_piece(self: &mut Self, piece: Option<Piece>, sq: Sq) {
+    pub fn parse_san(&self, san_str: &str) -> Result<Move, SanError> {
+        san::parse(self, san_str)
+    }
+
+    pub fn add_piece(&mut self, piece: Option<Piece>, sq: Sq) {
         if let Some(p) = piece {
             self.pos.piece[p as usize].set(sq as usize);
             zobrist::update(ZobristAction::TogglePiece(p, sq), self);
@@ -185,8 +235,37 @@ impl Board {
         println!("{}", castling_ltrs.iter().collect::<String>());
     }
 
+    /// Checks if the current position has occurred before since the last irreversible move.
+    /// `history` holds the undo records of the moves that led to this position, oldest first.
+    /// `search_ply` is the number of moves played since the search root; a single earlier
+    /// occurrence after the root counts as a draw, while positions from the game history
+    /// need to have occurred twice (threefold repetition).
+    pub fn is_repetition(&self, history: &[Undo], search_ply: usize) -> bool {
+        let len = history.len();
+        let limit = (self.state.half_moves as usize).min(len);
+        let mut count = 0;
+        // Only positions with the same side to move can be repetitions
+        for i in (2..=limit).step_by(2) {
+            let undo = &history[len - i];
+            if undo.key == self.state.key && undo.lock == self.state.lock {
+                if i < search_ply {
+                    return true;
+                }
+                count += 1;
+                if count == 2 {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.state.half_moves >= 100
+    }
+
     #[allow(dead_code)]
-    pub fn is_in_check(&self, attack_info: &AttackInfo, side: PieceColor) -> bool {
+    pub fn is_in_check(&self, side: PieceColor) -> bool {
         let king_type = if side == PieceColor::Light {
             Piece::DK
         } else {
@@ -194,51 +273,188 @@ impl Board {
         } as usize;
         sq_attacked(
             &self.pos,
-            attack_info,
             Sq::from_num(self.pos.piece[king_type].lsb()),
             side,
         )
     }
+
+    /// Static exchange evaluation: the material the side to move wins (or loses, if
+    /// negative) when `mv` starts a series of captures on its target square and both
+    /// sides keep recapturing with their least valuable piece for as long as it pays.
+    /// Sliders lined up behind a capturing piece join in once it has moved. Pins are
+    /// ignored, and so are promotions on recaptures.
+    pub fn see(&self, mv: Move) -> i32 {
+        if mv.is_castling() {
+            return 0;
+        }
+        let target = mv.target();
+        let (mut occupancy, mut on_square) = self.see_start(mv);
+        // gain[d] is what the side making capture d wins if the exchange stops there
+        let mut gain = [0; 32];
+        gain[0] = see_gain(mv, self.find_piece(target as usize));
+        let mut side = self.state.xside;
+        let mut depth = 0;
+        while let Some((sq, piece)) = least_valuable_attacker(&self.pos, target, occupancy, side)
+        {
+            depth += 1;
+            gain[depth] = on_square - gain[depth - 1];
+            on_square = SEE_VALUES[piece as usize % 6];
+            occupancy.pop(sq);
+            side = opposite(side);
+        }
+        // Each side only makes its capture if that beats stopping the exchange before it
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            depth -= 1;
+        }
+        gain[0]
+    }
+
+    /// Whether the static exchange evaluation of `mv` is at least `threshold`; the same
+    /// as comparing `see(mv)`, but done as soon as the result is certain
+    pub fn see_ge(&self, mv: Move, threshold: i32) -> bool {
+        if mv.is_castling() {
+            return threshold <= 0;
+        }
+        let target = mv.target();
+        // Even if nothing takes back, the move falls short
+        let balance = see_gain(mv, self.find_piece(target as usize)) - threshold;
+        if balance < 0 {
+            return false;
+        }
+        let (mut occupancy, on_square) = self.see_start(mv);
+        // What the other side wins by taking back; the move is safe if that isn't anything
+        let mut swap = on_square - balance;
+        if swap <= 0 {
+            return true;
+        }
+        // Whether the move passes if the exchange stops here, for lack of attackers or
+        // because the next capture doesn't pay
+        let mut result = true;
+        let mut side = self.state.xside;
+        while let Some((sq, piece)) = least_valuable_attacker(&self.pos, target, occupancy, side)
+        {
+            occupancy.pop(sq);
+            side = opposite(side);
+            result = !result;
+            swap = SEE_VALUES[piece as usize % 6] - swap;
+            if swap < result as i32 {
+                break;
+            }
+        }
+        result
+    }
+
+    // Occupancy once `mv` is played and the value of the piece it leaves on its target
+    fn see_start(&self, mv: Move) -> (BB, i32) {
+        let mut occupancy = self.pos.units(PieceColor::Both);
+        occupancy.pop(mv.source() as usize);
+        occupancy.set(mv.target() as usize);
+        if mv.is_enpassant() {
+            // The captured pawn is level with the capturing one
+            occupancy.pop(mv.source() as usize / 8 * 8 + mv.target() as usize % 8);
+        }
+        let moved = mv.promoted().unwrap_or(mv.piece());
+        (occupancy, SEE_VALUES[moved as usize % 6])
+    }
+}
+
+/// Piece values of the static exchange evaluation, by piece type. The king's only
+/// ensures that nothing is ever traded for it.
+pub const SEE_VALUES: [i32; 6] = [100, 300, 300, 500, 900, 20000];
+
+// Material won by the move itself, before any recapture
+fn see_gain(mv: Move, captured: Option<Piece>) -> i32 {
+    let mut gain = match captured {
+        Some(piece) => SEE_VALUES[piece as usize % 6],
+        None if mv.is_enpassant() => SEE_VALUES[0],
+        None => 0,
+    };
+    if let Some(promoted) = mv.promoted() {
+        gain += SEE_VALUES[promoted as usize % 6] - SEE_VALUES[0];
+    }
+    gain
 }
 
-pub fn sq_attacked(pos: &Position, attack_info: &AttackInfo, sq: Sq, side: PieceColor) -> bool {
+fn opposite(side: PieceColor) -> PieceColor {
+    if side == PieceColor::Light {
+        PieceColor::Dark
+    } else {
+        PieceColor::Light
+    }
+}
+
+// The cheapest piece of `side` that can capture on `sq` with the given occupancy. Pieces
+// missing from the occupancy have been traded off already. A king can't capture on a
+// square the other side still attacks.
+fn least_valuable_attacker(
+    pos: &Position,
+    sq: Sq,
+    occupancy: BB,
+    side: PieceColor,
+) -> Option<(usize, Piece)> {
+    let attackers = sq_attackers(pos, sq, occupancy) & occupancy;
+    let offset = side as usize * 6;
+    let piece = (offset..offset + 6).find(|&piece| attackers & pos.piece[piece] != 0)?;
+    if piece % 6 == 5 && attackers & pos.units(opposite(side)) != 0 {
+        return None;
+    }
+    Some(((attackers & pos.piece[piece]).lsb(), Piece::from_num(piece)?))
+}
+
+pub fn sq_attacked(pos: &Position, sq: Sq, side: PieceColor) -> bool {
     assert!(side != PieceColor::Both);
     let both_units = pos.units(PieceColor::Both);
     if side == PieceColor::Light
-        && ((attack_info.pawn[PieceColor::Dark as usize][sq as usize]
+        && ((attack::pawn_attacks(PieceColor::Dark, sq as usize)
             & pos.piece[Piece::LP as usize])
             != 0)
     {
         return true;
     }
     if side == PieceColor::Dark
-        && ((attack_info.pawn[PieceColor::Light as usize][sq as usize]
+        && ((attack::pawn_attacks(PieceColor::Light, sq as usize)
             & pos.piece[Piece::DP as usize])
             != 0)
     {
         return true;
     }
-    if (attack_info.knight[sq as usize] & pos.piece[(side as usize) * 6 + 1]) != 0 {
+    if (attack::knight_attacks(sq as usize) & pos.piece[(side as usize) * 6 + 1]) != 0 {
         return true;
     }
-    if (attack_info.get_bishop_attack(sq, both_units) & pos.piece[(side as usize) * 6 + 2]) != 0 {
+    if (attack::bishop_attacks(sq, both_units) & pos.piece[(side as usize) * 6 + 2]) != 0 {
         return true;
     }
-    if (attack_info.get_rook_attack(sq, both_units) & pos.piece[(side as usize) * 6 + 3]) != 0 {
+    if (attack::rook_attacks(sq, both_units) & pos.piece[(side as usize) * 6 + 3]) != 0 {
         return true;
     }
-    if (attack_info.get_queen_attack(sq, both_units) & pos.piece[(side as usize) * 6 + 4]) != 0 {
+    if (attack::queen_attacks(sq, both_units) & pos.piece[(side as usize) * 6 + 4]) != 0 {
         return true;
     }
-    if (attack_info.king[sq as usize] & pos.piece[(side as usize) * 6 + 5]) != 0 {
+    if (attack::king_attacks(sq as usize) & pos.piece[(side as usize) * 6 + 5]) != 0 {
         return true;
     }
     false
 }
 
+/// Pieces of both sides attacking a square with the given occupancy, which lets
+/// the caller see through pieces that are (or will be) moved away
+pub fn sq_attackers(pos: &Position, sq: Sq, occupancy: BB) -> BB {
+    let piece = &pos.piece;
+    let bishops = piece[Piece::LB as usize] | piece[Piece::DB as usize];
+    let rooks = piece[Piece::LR as usize] | piece[Piece::DR as usize];
+    let queens = piece[Piece::LQ as usize] | piece[Piece::DQ as usize];
+    (attack::pawn_attacks(PieceColor::Dark, sq as usize) & piece[Piece::LP as usize])
+        | (attack::pawn_attacks(PieceColor::Light, sq as usize) & piece[Piece::DP as usize])
+        | (attack::knight_attacks(sq as usize) & (piece[Piece::LN as usize] | piece[Piece::DN as usize]))
+        | (attack::bishop_attacks(sq, occupancy) & (bishops | queens))
+        | (attack::rook_attacks(sq, occupancy) & (rooks | queens))
+        | (attack::king_attacks(sq as usize) & (piece[Piece::LK as usize] | piece[Piece::DK as usize]))
+}
+
 /// `side` refers to the attacking side
 #[allow(dead_code)]
-pub fn print_attacked_sqs(board: &Board, attack_info: &AttackInfo, side: PieceColor) {
+pub fn print_attacked_sqs(board: &Board, side: PieceColor) {
     for r in 0..8 {
         for f in 0..8 {
             let sq = SQ!(r, f);
@@ -247,7 +463,7 @@ pub fn print_attacked_sqs(board: &Board, attack_info: &AttackInfo, side: PieceCo
             }
             print!(
                 " {}",
-                if sq_attacked(&board.pos, attack_info, Sq::from_num(sq), side) {
+                if sq_attacked(&board.pos, Sq::from_num(sq), side) {
                     '1'
                 } else {
                     '.'
@@ -260,7 +476,7 @@ pub fn print_attacked_sqs(board: &Board, attack_info: &AttackInfo, side: PieceCo
     println!("     a b c d e f g h\n");
 }
 
-pub fn in_check(board: &Board, attack_info: &AttackInfo, checked_by: PieceColor) -> bool {
+pub fn in_check(board: &Board, checked_by: PieceColor) -> bool {
     let king_type = if checked_by == PieceColor::Light {
         Piece::DK
     } else {
@@ -268,7 +484,6 @@ pub fn in_check(board: &Board, attack_info: &AttackInfo, checked_by: PieceColor)
     } as usize;
     sq_attacked(
         &board.pos,
-        attack_info,
         Sq::from_num(board.pos.piece[king_type].lsb()),
         checked_by,
     )
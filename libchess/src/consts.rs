@@ -162,7 +162,7 @@ pub enum Direction {
 }
 
 #[rustfmt::skip]
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Sq {
     A8, B8, C8, D8, E8, F8, G8, H8,
     A7, B7, C7, D7, E7, F7, G7, H7,
@@ -182,6 +182,7 @@ impl fmt::Display for Sq {
 }
 
 impl Sq {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(sq_str: &str) -> Sq {
         assert!(sq_str.len() == 2);
         let file = (sq_str.chars().nth(0).unwrap() as u8) - b'a';
@@ -189,6 +190,15 @@ impl Sq {
         Self::from_num(SQ!(rank, file) as usize)
     }
 
+    // Same as `from_str()`, but returns None instead of panicking on an invalid square
+    pub fn parse(sq_str: &str) -> Option<Sq> {
+        let bytes = sq_str.as_bytes();
+        if bytes.len() != 2 || !(b'a'..=b'h').contains(&bytes[0]) || !(b'1'..=b'8').contains(&bytes[1]) {
+            return None;
+        }
+        Some(Self::from_str(sq_str))
+    }
+
     pub fn from_num(sq_num: usize) -> Self {
         match sq_num {
             0 => Self::A8,
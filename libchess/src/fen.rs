@@ -1,6 +1,10 @@
-use crate::board::{Board, CastlingType};
+use crate::bb::{BBUtil, BB};
+use crate::board::{self, Board, CastlingType};
 use crate::consts::{Piece, PieceColor, Sq};
 use crate::zobrist::{self, ZobristAction};
+use crate::{ROW, SQ};
+
+use std::fmt;
 
 pub const FEN_POSITIONS: [&str; 8] = [
     "8/8/8/8/8/8/8/8 w - - 0 1",
@@ -13,75 +17,268 @@ pub const FEN_POSITIONS: [&str; 8] = [
     "rnbqkb1r/pp1p1pPp/8/2p1pP2/1P1P4/3P3P/P1P1P3/RNBQKBNR w KQkq e6 0 1",
 ];
 
-pub fn parse(fen: &str, board: &mut Board) {
+#[derive(Debug, PartialEq)]
+pub enum FenError {
+    MissingField(&'static str),
+    InvalidPiece(char),
+    // Rank number (8 to 1) whose squares don't add up to 8
+    InvalidRankLength(usize),
+    InvalidRankCount(usize),
+    InvalidSide(String),
+    InvalidCastling(String),
+    InvalidEnpassant(String),
+    InvalidHalfMoves(String),
+    InvalidFullMoves(String),
+    InvalidKingCount(&'static str, u32),
+    PawnOnBackRank(Sq),
+    OpponentInCheck,
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingField(field) => write!(f, "missing {} field", field),
+            Self::InvalidPiece(c) => write!(f, "invalid piece character '{}'", c),
+            Self::InvalidRankLength(rank) => {
+                write!(f, "rank {} doesn't have exactly 8 squares", rank)
+            }
+            Self::InvalidRankCount(count) => write!(f, "expected 8 ranks, got {}", count),
+            Self::InvalidSide(side) => write!(f, "invalid side to move '{}'", side),
+            Self::InvalidCastling(castling) => write!(f, "invalid castling rights '{}'", castling),
+            Self::InvalidEnpassant(sq) => write!(f, "invalid enpassant square '{}'", sq),
+            Self::InvalidHalfMoves(n) => write!(f, "invalid half move clock '{}'", n),
+            Self::InvalidFullMoves(n) => write!(f, "invalid full move number '{}'", n),
+            Self::InvalidKingCount(side, count) => {
+                write!(f, "{} has {} kings, expected exactly one", side, count)
+            }
+            Self::PawnOnBackRank(sq) => write!(f, "pawn on back rank square {}", sq),
+            Self::OpponentInCheck => write!(f, "side not to move is in check"),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+// Only checks the syntax of each field; see `validate()` for the legality checks
+pub fn parse(fen: &str, board: &mut Board) -> Result<(), FenError> {
     let mut fen_parts = fen.split_ascii_whitespace();
 
     // Place piece on square
-    parse_pieces(fen_parts.next().unwrap(), board);
+    let pieces_str = fen_parts.next().ok_or(FenError::MissingField("pieces"))?;
+    parse_pieces(pieces_str, board)?;
 
     // Set side to move
-    let side_to_move_str: &str = fen_parts.next().unwrap();
-    if side_to_move_str == "w" {
-        board.state.side = PieceColor::Light;
-    } else if side_to_move_str == "b" {
-        board.state.side = PieceColor::Dark;
-        zobrist::update(ZobristAction::ChangeColor, board);
+    let side_to_move_str = fen_parts.next().ok_or(FenError::MissingField("side"))?;
+    match side_to_move_str {
+        "w" => board.state.side = PieceColor::Light,
+        "b" => {
+            board.state.side = PieceColor::Dark;
+            zobrist::update(ZobristAction::ChangeColor, board);
+        }
+        _ => return Err(FenError::InvalidSide(side_to_move_str.to_string())),
     }
+    board.state.xside = if board.state.side == PieceColor::Light {
+        PieceColor::Dark
+    } else {
+        PieceColor::Light
+    };
 
     // Set castling right
-    for castling_type in fen_parts.next().unwrap().chars() {
-        if castling_type == 'K' {
-            board
-                .state
-                .toggle_castling(CastlingType::WhiteKingside as usize);
-        } else if castling_type == 'Q' {
-            board
-                .state
-                .toggle_castling(CastlingType::WhiteQueenside as usize);
-        } else if castling_type == 'k' {
-            board
-                .state
-                .toggle_castling(CastlingType::BlackKingside as usize);
-        } else if castling_type == 'q' {
-            board
-                .state
-                .toggle_castling(CastlingType::BlackQueenside as usize);
-        }
-    }
+    let castling_str = fen_parts.next().ok_or(FenError::MissingField("castling"))?;
+    parse_castling(castling_str, board)?;
     zobrist::update(ZobristAction::Castling, board);
 
     // Set enpassant square
-    let enpass_square = fen_parts.next().unwrap();
+    let enpass_square = fen_parts.next().ok_or(FenError::MissingField("enpassant"))?;
     if enpass_square != "-" {
-        board.state.enpassant = Sq::from_str(enpass_square);
+        board.state.enpassant = parse_enpassant(enpass_square, board)?;
     }
     zobrist::update(ZobristAction::Enpassant, board);
+
+    // The move counters are optional since a lot of tools leave them out
     // Set 50 move rule
-    let half_moves = fen_parts.next().unwrap().parse::<u32>().unwrap();
-    board.state.half_moves = half_moves;
+    if let Some(half_moves) = fen_parts.next() {
+        board.state.half_moves = half_moves
+            .parse::<u32>()
+            .map_err(|_| FenError::InvalidHalfMoves(half_moves.to_string()))?;
+    }
     // Set move counter
-    let full_moves = fen_parts.next().unwrap().parse::<u32>().unwrap();
-    board.state.full_moves = full_moves;
+    board.state.full_moves = 1;
+    if let Some(full_moves) = fen_parts.next() {
+        board.state.full_moves = full_moves
+            .parse::<u32>()
+            .map_err(|_| FenError::InvalidFullMoves(full_moves.to_string()))?;
+    }
+    Ok(())
 }
 
-fn parse_pieces(fen_piece: &str, board: &mut Board) {
-    let mut sq: usize = 0;
-    for piece_char in fen_piece.chars() {
-        if piece_char == '/' {
-            continue;
-        } else if piece_char.is_ascii_digit() {
-            // Retrieve the int value of the offset from the char value
-            let offset = piece_char as u8 - b'0';
-            // Add offset value to square counter
-            sq += offset as usize;
-        } else if piece_char.is_ascii_alphabetic() {
-            let (piece_color, _) = Piece::to_tuple(Piece::from_char(piece_char));
-            if piece_color == PieceColor::Both as usize {
-                continue;
+pub fn serialize(board: &Board) -> String {
+    let mut fen = String::new();
+
+    // Piece placement from the 8th rank to the 1st
+    for r in 0..8 {
+        let mut empty = 0;
+        for f in 0..8 {
+            match board.find_piece(SQ!(r, f)) {
+                Some(piece) => {
+                    if empty > 0 {
+                        fen.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    fen.push(Piece::to_char(Some(piece)));
+                }
+                None => empty += 1,
             }
-            board.add_piece(Piece::from_char(piece_char), Sq::from_num(sq));
-            // Increment the current square
-            sq += 1;
+        }
+        if empty > 0 {
+            fen.push_str(&empty.to_string());
+        }
+        if r != 7 {
+            fen.push('/');
         }
     }
+
+    // Side to move
+    fen.push_str(if board.state.side == PieceColor::Light {
+        " w "
+    } else {
+        " b "
+    });
+
+    // Castling rights
+    if board.state.castling == 0 {
+        fen.push('-');
+    }
+    let castling = board.state.castling as BB;
+    for (castling_type, castling_char) in [
+        (CastlingType::WhiteKingside, 'K'),
+        (CastlingType::WhiteQueenside, 'Q'),
+        (CastlingType::BlackKingside, 'k'),
+        (CastlingType::BlackQueenside, 'q'),
+    ] {
+        if castling.get(castling_type as usize) {
+            fen.push(castling_char);
+        }
+    }
+
+    // Enpassant square
+    if board.state.enpassant == Sq::NoSq {
+        fen.push_str(" -");
+    } else {
+        fen.push_str(&format!(" {}", board.state.enpassant));
+    }
+
+    // Half move clock and full move number
+    fen.push_str(&format!(
+        " {} {}",
+        board.state.half_moves, board.state.full_moves
+    ));
+    fen
+}
+
+fn parse_pieces(fen_piece: &str, board: &mut Board) -> Result<(), FenError> {
+    let ranks: Vec<&str> = fen_piece.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(FenError::InvalidRankCount(ranks.len()));
+    }
+    for (r, rank_str) in ranks.iter().enumerate() {
+        let mut f: usize = 0;
+        for piece_char in rank_str.chars() {
+            if let Some(offset) = piece_char.to_digit(10).filter(|n| (1..=8).contains(n)) {
+                // Skip the number of empty squares
+                f += offset as usize;
+            } else if "PNBRQKpnbrqk".contains(piece_char) {
+                if f >= 8 {
+                    return Err(FenError::InvalidRankLength(8 - r));
+                }
+                board.add_piece(Piece::from_char(piece_char), Sq::from_num(SQ!(r, f)));
+                // Increment the current square
+                f += 1;
+            } else {
+                return Err(FenError::InvalidPiece(piece_char));
+            }
+        }
+        if f != 8 {
+            return Err(FenError::InvalidRankLength(8 - r));
+        }
+    }
+    Ok(())
+}
+
+fn parse_castling(castling_str: &str, board: &mut Board) -> Result<(), FenError> {
+    if castling_str == "-" {
+        return Ok(());
+    }
+    let err = || FenError::InvalidCastling(castling_str.to_string());
+    for castling_type in castling_str.chars() {
+        // The king and the rook need to be on their starting squares for the right to exist
+        let (castling, king, king_sq, rook, rook_sq) = match castling_type {
+            'K' => (CastlingType::WhiteKingside, Piece::LK, Sq::E1, Piece::LR, Sq::H1),
+            'Q' => (CastlingType::WhiteQueenside, Piece::LK, Sq::E1, Piece::LR, Sq::A1),
+            'k' => (CastlingType::BlackKingside, Piece::DK, Sq::E8, Piece::DR, Sq::H8),
+            'q' => (CastlingType::BlackQueenside, Piece::DK, Sq::E8, Piece::DR, Sq::A8),
+            _ => return Err(err()),
+        };
+        let castling = castling as usize;
+        if (board.state.castling as BB).get(castling)
+            || !board.pos.piece[king as usize].get(king_sq as usize)
+            || !board.pos.piece[rook as usize].get(rook_sq as usize)
+        {
+            return Err(err());
+        }
+        board.state.toggle_castling(castling);
+    }
+    Ok(())
+}
+
+fn parse_enpassant(sq_str: &str, board: &Board) -> Result<Sq, FenError> {
+    let err = || FenError::InvalidEnpassant(sq_str.to_string());
+    let sq = Sq::parse(sq_str).ok_or_else(err)?;
+    // Enpassant square has to be right behind the pawn that just made a two square push
+    let (rank, pawn, direction) = if board.state.side == PieceColor::Light {
+        (2, Piece::DP, 8)
+    } else {
+        (5, Piece::LP, -8)
+    };
+    if ROW!(sq as usize) != rank {
+        return Err(err());
+    }
+    let pawn_sq = (sq as i32 + direction) as usize;
+    if !board.pos.piece[pawn as usize].get(pawn_sq) {
+        return Err(err());
+    }
+    Ok(sq)
+}
+
+// Legality checks on top of the syntax checks done while parsing
+pub fn validate(board: &Board) -> Result<(), FenError> {
+    for (king, side) in [(Piece::LK, "white"), (Piece::DK, "black")] {
+        let count = board.pos.piece[king as usize].count_ones();
+        if count != 1 {
+            return Err(FenError::InvalidKingCount(side, count));
+        }
+    }
+
+    // First and eighth rank
+    const BACK_RANKS: BB = 0xFF000000000000FF;
+    let pawns = board.pos.piece[Piece::LP as usize] | board.pos.piece[Piece::DP as usize];
+    if pawns & BACK_RANKS != 0 {
+        return Err(FenError::PawnOnBackRank(Sq::from_num((pawns & BACK_RANKS).lsb())));
+    }
+
+    if king_capturable(board) {
+        return Err(FenError::OpponentInCheck);
+    }
+    Ok(())
+}
+
+// Checks if the side to move attacks the opponent's king
+fn king_capturable(board: &Board) -> bool {
+    let king = if board.state.side == PieceColor::Light {
+        Piece::DK
+    } else {
+        Piece::LK
+    };
+    let sq = Sq::from_num(board.pos.piece[king as usize].lsb());
+    board::sq_attacked(&board.pos, sq, board.state.side)
 }
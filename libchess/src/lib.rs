@@ -6,6 +6,8 @@ pub mod fen;
 mod magics;
 pub mod move_gen;
 pub mod moves;
+pub mod pgn;
 mod prng;
+pub mod san;
 pub mod zobrist;
 mod wasm_exports;
@@ -1,4 +1,4 @@
-use crate::attack::AttackInfo;
+use crate::attack;
 use crate::bb::{BBUtil, BB};
 use crate::board::{self, Board, CastlingType};
 use crate::consts::{Direction, Piece, PieceColor, Sq};
@@ -8,6 +8,12 @@ pub struct MoveList {
     pub moves: Vec<Move>,
 }
 
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MoveList {
     pub fn new() -> Self {
         Self { moves: vec![] }
@@ -41,16 +47,180 @@ impl MoveList {
     }
 }
 
-pub fn generate(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
-    generate_pawns(board, attack_info, ml);
-    generate_knights(board, attack_info, ml);
-    generate_bishops(board, attack_info, ml);
-    generate_rooks(board, attack_info, ml);
-    generate_queens(board, attack_info, ml);
-    generate_kings(board, attack_info, ml);
+/// Which part of the pseudo-legal moves to generate. `Captures` and `Quiets` split
+/// `All` exactly: every capture and every promotion to a queen counts as a capture,
+/// under-promotions that don't capture as quiets.
+#[derive(Clone, Copy, PartialEq)]
+enum GenType {
+    All,
+    Captures,
+    Quiets,
+}
+
+impl GenType {
+    // Squares pieces other than pawns may move to
+    fn targets(self, board: &Board) -> BB {
+        match self {
+            Self::All => !board.pos.units(board.state.side),
+            Self::Captures => board.pos.units(board.state.xside),
+            Self::Quiets => !board.pos.units(PieceColor::Both),
+        }
+    }
+}
+
+pub fn generate(board: &Board, ml: &mut MoveList) {
+    generate_by_type(board, ml, GenType::All);
+}
+
+/// Pseudo-legal captures (including en passant and capturing under-promotions) and queen
+/// promotions, for quiescence search
+pub fn generate_captures(board: &Board, ml: &mut MoveList) {
+    generate_by_type(board, ml, GenType::Captures);
+}
+
+/// Pseudo-legal moves not produced by `generate_captures`: quiet moves, castling and
+/// under-promotions that don't capture
+pub fn generate_quiets(board: &Board, ml: &mut MoveList) {
+    generate_by_type(board, ml, GenType::Quiets);
+}
+
+fn generate_by_type(board: &Board, ml: &mut MoveList, gen_type: GenType) {
+    generate_pawns(board, ml, gen_type);
+    generate_knights(board, ml, gen_type);
+    generate_bishops(board, ml, gen_type);
+    generate_rooks(board, ml, gen_type);
+    generate_queens(board, ml, gen_type);
+    generate_kings(board, ml, gen_type);
+}
+
+/// Whether `mv` is one of the moves `generate` would produce in this position. Moves that
+/// come from somewhere other than the generator, like a hash move, can be checked with this
+/// before they are played.
+pub fn is_pseudo_legal(board: &Board, mv: Move) -> bool {
+    // Zero is used as "no move" and isn't a valid encoding
+    if mv == 0 {
+        return false;
+    }
+    let piece = mv.piece();
+    if piece as usize / 6 != board.state.side as usize
+        || !board.pos.piece[piece as usize].get(mv.source() as usize)
+    {
+        return false;
+    }
+    // Only the moves of the moving piece type need to be looked at
+    let mut ml = MoveList::new();
+    match piece {
+        Piece::LP | Piece::DP => generate_pawns(board, &mut ml, GenType::All),
+        Piece::LN | Piece::DN => generate_knights(board, &mut ml, GenType::All),
+        Piece::LB | Piece::DB => generate_bishops(board, &mut ml, GenType::All),
+        Piece::LR | Piece::DR => generate_rooks(board, &mut ml, GenType::All),
+        Piece::LQ | Piece::DQ => generate_queens(board, &mut ml, GenType::All),
+        Piece::LK | Piece::DK => generate_kings(board, &mut ml, GenType::All),
+    }
+    ml.moves.contains(&mv)
 }
 
-fn generate_pawns(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
+/// Generates only legal moves. Checkers and pinned pieces are worked out once up front,
+/// so callers never have to play a move to find out whether it leaves the king in check.
+pub fn generate_legal(board: &Board, ml: &mut MoveList) {
+    let is_white = board.state.side == PieceColor::Light;
+    let king = if is_white { Piece::LK } else { Piece::DK };
+    let king_bb = board.pos.piece[king as usize];
+    if king_bb == 0 {
+        // Without a king nothing can be illegal
+        generate(board, ml);
+        return;
+    }
+    let king_sq = king_bb.lsb();
+    let own_units = board.pos.units(board.state.side);
+    let enemy_units = board.pos.units(board.state.xside);
+    let occupancy = board.pos.units(PieceColor::Both);
+
+    let checkers = board::sq_attackers(&board.pos, Sq::from_num(king_sq), occupancy)
+        & enemy_units;
+
+    // Squares a non-king move has to land on: anywhere when not in check, the checker or a
+    // square blocking it when in single check and nowhere at all when in double check
+    let evasion_mask = match checkers.count_ones() {
+        0 => !0,
+        1 => checkers | attack::between(king_sq, checkers.lsb()),
+        _ => 0,
+    };
+
+    // A pinned piece may only move along the line between its king and the pinning slider
+    let (pinned, pin_rays) = pins(board, king_sq, own_units, enemy_units);
+
+    let mut pseudo_legal = MoveList::new();
+    generate(board, &mut pseudo_legal);
+    for mv in pseudo_legal.moves {
+        let source = mv.source() as usize;
+        let target = mv.target() as usize;
+        let is_legal = if mv.piece() == king {
+            if mv.is_castling() {
+                // The passing squares are checked by the castling generator
+                checkers == 0
+                    && !board::sq_attacked(&board.pos, mv.target(), board.state.xside)
+            } else {
+                // Sliders must see through the king, or it could step back along their ray
+                let occupancy = occupancy & !(1 << source);
+                board::sq_attackers(&board.pos, mv.target(), occupancy)
+                    & enemy_units
+                    == 0
+            }
+        } else if mv.is_enpassant() {
+            // Two pieces leave the same rank at once, which pin masks can't describe,
+            // so replay the capture on the occupancy instead
+            let captured_sq = if is_white { target + 8 } else { target - 8 };
+            let occupancy = (occupancy & !(1 << source) & !(1 << captured_sq)) | (1 << target);
+            board::sq_attackers(&board.pos, Sq::from_num(king_sq), occupancy)
+                & enemy_units
+                & !(1 << captured_sq)
+                == 0
+        } else {
+            evasion_mask.get(target) && (!pinned.get(source) || pin_rays[source].get(target))
+        };
+        if is_legal {
+            ml.moves.push(mv);
+        }
+    }
+}
+
+fn pins(
+    board: &Board,
+    king_sq: usize,
+    own_units: BB,
+    enemy_units: BB,
+) -> (BB, [BB; 64]) {
+    let piece = &board.pos.piece;
+    let (bishops, rooks, queens) = if board.state.side == PieceColor::Light {
+        (Piece::DB, Piece::DR, Piece::DQ)
+    } else {
+        (Piece::LB, Piece::LR, Piece::LQ)
+    };
+    let diagonal = piece[bishops as usize] | piece[queens as usize];
+    let straight = piece[rooks as usize] | piece[queens as usize];
+
+    // Enemy sliders that would attack the king if none of our pieces were in the way
+    let king = Sq::from_num(king_sq);
+    let mut snipers = (attack::bishop_attacks(king, enemy_units) & diagonal)
+        | (attack::rook_attacks(king, enemy_units) & straight);
+
+    let mut pinned = 0;
+    let mut pin_rays = [0; 64];
+    while snipers > 0 {
+        let sniper_sq = snipers.pop_lsb();
+        let ray = attack::between(king_sq, sniper_sq);
+        let blockers = ray & (own_units | enemy_units);
+        if blockers.count_ones() == 1 && blockers & own_units != 0 {
+            let pinned_sq = blockers.lsb();
+            pinned.set(pinned_sq);
+            pin_rays[pinned_sq] = ray | (1 << sniper_sq);
+        }
+    }
+    (pinned, pin_rays)
+}
+
+fn generate_pawns(board: &Board, ml: &mut MoveList, gen_type: GenType) {
     const PROMOTED_PIECE_LIST: [[Piece; 4]; 2] = [
         [Piece::LQ, Piece::LR, Piece::LB, Piece::LN],
         [Piece::DQ, Piece::DR, Piece::DB, Piece::DN],
@@ -81,6 +251,18 @@ fn generate_pawns(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
         direction = Direction::North;
     }
 
+    // Queen promotions are generated along with the captures, and so are all promotions
+    // that capture
+    let promotions = match gen_type {
+        GenType::All => 0..4,
+        GenType::Captures => 0..1,
+        GenType::Quiets => 1..4,
+    };
+    let capture_promotions = match gen_type {
+        GenType::All | GenType::Captures => 0..4,
+        GenType::Quiets => 0..0,
+    };
+
     bb_copy = board.pos.piece[piece as usize];
     let mut source: i32;
     let mut target: i32;
@@ -97,19 +279,19 @@ fn generate_pawns(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
             // If true, this move is a promotion
             if (source >= promotion_start as i32) && (source <= (promotion_start as i32 + 7)) {
                 let ind = if is_white { 0 } else { 1 };
-                for i in 0..4 {
+                for promoted in &PROMOTED_PIECE_LIST[ind][promotions.clone()] {
                     ml.moves.push(Move::encode(
                         Sq::from_num(source as usize),
                         Sq::from_num(target as usize),
                         piece,
-                        Some(PROMOTED_PIECE_LIST[ind][i]),
+                        Some(*promoted),
                         false,
                         false,
                         false,
                         false,
                     ));
                 }
-            } else {
+            } else if gen_type != GenType::Captures {
                 // If false, this is a normal(quiet or non-capture) move
                 ml.moves.push(Move::encode(
                     Sq::from_num(source as usize),
@@ -143,26 +325,26 @@ fn generate_pawns(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
             }
         }
 
-        attack_copy = attack_info.pawn[board.state.side as usize][source as usize]
+        attack_copy = attack::pawn_attacks(board.state.side, source as usize)
             & board.pos.units(enemy_color);
         while attack_copy > 0 {
             target = attack_copy.pop_lsb() as i32;
             if (source >= promotion_start as i32) && (source <= (promotion_start as i32 + 7)) {
                 // If true, this move is a capture promotion
                 let ind = if is_white { 0 } else { 1 };
-                for i in 0..4 {
+                for promoted in &PROMOTED_PIECE_LIST[ind][capture_promotions.clone()] {
                     ml.moves.push(Move::encode(
                         Sq::from_num(source as usize),
                         Sq::from_num(target as usize),
                         piece,
-                        Some(PROMOTED_PIECE_LIST[ind][i]),
+                        Some(*promoted),
                         true,
                         false,
                         false,
                         false,
                     ));
                 }
-            } else {
+            } else if gen_type != GenType::Quiets {
                 // If false, this move is a normal capture
                 ml.moves.push(Move::encode(
                     Sq::from_num(source as usize),
@@ -177,8 +359,8 @@ fn generate_pawns(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
                 ));
             }
         }
-        if board.state.enpassant != Sq::NoSq {
-            let enpassant_capture = attack_info.pawn[board.state.side as usize][source as usize]
+        if board.state.enpassant != Sq::NoSq && gen_type != GenType::Quiets {
+            let enpassant_capture = attack::pawn_attacks(board.state.side, source as usize)
                 & (1 << (board.state.enpassant as usize));
             if enpassant_capture != 0 {
                 let enpassant_target = enpassant_capture.lsb();
@@ -197,16 +379,11 @@ fn generate_pawns(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
     }
 }
 
-fn generate_knights(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
+fn generate_knights(board: &Board, ml: &mut MoveList, gen_type: GenType) {
     let mut source;
     let mut target;
     let is_white = board.state.side == PieceColor::Light;
     let piece = if is_white { Piece::LN } else { Piece::DN };
-    let color = if is_white {
-        PieceColor::Light
-    } else {
-        PieceColor::Dark
-    };
     let enemy_color = if is_white {
         PieceColor::Dark
     } else {
@@ -217,7 +394,7 @@ fn generate_knights(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList)
 
     while bb_copy > 0 {
         source = bb_copy.pop_lsb();
-        attack_copy = attack_info.knight[source] & (!board.pos.units(color));
+        attack_copy = attack::knight_attacks(source) & gen_type.targets(board);
         while attack_copy > 0 {
             target = attack_copy.pop_lsb();
             let is_capture_move = board.pos.units(enemy_color).get(target);
@@ -235,16 +412,11 @@ fn generate_knights(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList)
     }
 }
 
-fn generate_bishops(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
+fn generate_bishops(board: &Board, ml: &mut MoveList, gen_type: GenType) {
     let mut source;
     let mut target;
     let is_white = board.state.side == PieceColor::Light;
     let piece = if is_white { Piece::LB } else { Piece::DB };
-    let color = if is_white {
-        PieceColor::Light
-    } else {
-        PieceColor::Dark
-    };
     let enemy_color = if is_white {
         PieceColor::Dark
     } else {
@@ -255,9 +427,8 @@ fn generate_bishops(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList)
 
     while bb_copy > 0 {
         source = bb_copy.pop_lsb();
-        attack_copy = attack_info
-            .get_bishop_attack(Sq::from_num(source), board.pos.units(PieceColor::Both))
-            & (!board.pos.units(color));
+        attack_copy = attack::bishop_attacks(Sq::from_num(source), board.pos.units(PieceColor::Both))
+            & gen_type.targets(board);
         while attack_copy > 0 {
             target = attack_copy.pop_lsb();
             let is_capture_move = board.pos.units(enemy_color).get(target);
@@ -275,16 +446,11 @@ fn generate_bishops(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList)
     }
 }
 
-fn generate_rooks(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
+fn generate_rooks(board: &Board, ml: &mut MoveList, gen_type: GenType) {
     let mut source;
     let mut target;
     let is_white = board.state.side == PieceColor::Light;
     let piece = if is_white { Piece::LR } else { Piece::DR };
-    let color = if is_white {
-        PieceColor::Light
-    } else {
-        PieceColor::Dark
-    };
     let enemy_color = if is_white {
         PieceColor::Dark
     } else {
@@ -295,9 +461,8 @@ fn generate_rooks(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
 
     while bb_copy > 0 {
         source = bb_copy.pop_lsb();
-        attack_copy = attack_info
-            .get_rook_attack(Sq::from_num(source), board.pos.units(PieceColor::Both))
-            & (!board.pos.units(color));
+        attack_copy = attack::rook_attacks(Sq::from_num(source), board.pos.units(PieceColor::Both))
+            & gen_type.targets(board);
         while attack_copy > 0 {
             target = attack_copy.pop_lsb();
             let is_capture_move = board.pos.units(enemy_color).get(target);
@@ -315,16 +480,11 @@ fn generate_rooks(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
     }
 }
 
-fn generate_queens(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
+fn generate_queens(board: &Board, ml: &mut MoveList, gen_type: GenType) {
     let mut source;
     let mut target;
     let is_white = board.state.side == PieceColor::Light;
     let piece = if is_white { Piece::LQ } else { Piece::DQ };
-    let color = if is_white {
-        PieceColor::Light
-    } else {
-        PieceColor::Dark
-    };
     let enemy_color = if is_white {
         PieceColor::Dark
     } else {
@@ -335,9 +495,8 @@ fn generate_queens(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
 
     while bb_copy > 0 {
         source = bb_copy.pop_lsb();
-        attack_copy = attack_info
-            .get_queen_attack(Sq::from_num(source), board.pos.units(PieceColor::Both))
-            & (!board.pos.units(color));
+        attack_copy = attack::queen_attacks(Sq::from_num(source), board.pos.units(PieceColor::Both))
+            & gen_type.targets(board);
         while attack_copy > 0 {
             target = attack_copy.pop_lsb();
             let is_capture_move = board.pos.units(enemy_color).get(target);
@@ -355,16 +514,11 @@ fn generate_queens(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
     }
 }
 
-fn generate_kings(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
+fn generate_kings(board: &Board, ml: &mut MoveList, gen_type: GenType) {
     let mut source;
     let mut target;
     let is_white = board.state.side == PieceColor::Light;
     let piece = if is_white { Piece::LK } else { Piece::DK };
-    let color = if is_white {
-        PieceColor::Light
-    } else {
-        PieceColor::Dark
-    };
     let enemy_color = if is_white {
         PieceColor::Dark
     } else {
@@ -375,7 +529,7 @@ fn generate_kings(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
 
     while bb_copy > 0 {
         source = bb_copy.pop_lsb();
-        attack_copy = attack_info.king[source] & (!board.pos.units(color));
+        attack_copy = attack::king_attacks(source) & gen_type.targets(board);
         while attack_copy > 0 {
             target = attack_copy.pop_lsb();
             let is_capture_move = board.pos.units(enemy_color).get(target);
@@ -391,67 +545,62 @@ fn generate_kings(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
             ));
         }
     }
+    if gen_type == GenType::Captures {
+        return;
+    }
     if is_white {
-        gen_light_castling(board, attack_info, ml);
+        gen_light_castling(board, ml);
     } else {
-        gen_dark_castling(board, attack_info, ml);
+        gen_dark_castling(board, ml);
     }
 }
 
-fn gen_light_castling(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
+fn gen_light_castling(board: &Board, ml: &mut MoveList) {
     let castling = board.state.castling as BB;
     let both_units = board.pos.units(PieceColor::Both);
-    if castling.get(CastlingType::WhiteKingside as usize) {
-        if !both_units.get(Sq::F1 as usize) && !both_units.get(Sq::G1 as usize) {
-            if !board::sq_attacked(&board.pos, attack_info, Sq::E1, PieceColor::Dark)
-                && !board::sq_attacked(&board.pos, attack_info, Sq::F1, PieceColor::Dark)
-            {
-                ml.moves
-                    .push(Move::from_str("e1g1", Piece::LK, false, false, false, true));
-            }
-        }
+    if castling.get(CastlingType::WhiteKingside as usize)
+        && !both_units.get(Sq::F1 as usize)
+        && !both_units.get(Sq::G1 as usize)
+        && !board::sq_attacked(&board.pos, Sq::E1, PieceColor::Dark)
+        && !board::sq_attacked(&board.pos, Sq::F1, PieceColor::Dark)
+    {
+        ml.moves
+            .push(Move::from_str("e1g1", Piece::LK, false, false, false, true));
     }
 
-    if castling.get(CastlingType::WhiteQueenside as usize) {
-        if !both_units.get(Sq::B1 as usize)
-            && !both_units.get(Sq::C1 as usize)
-            && !both_units.get(Sq::D1 as usize)
-        {
-            if !board::sq_attacked(&board.pos, attack_info, Sq::D1, PieceColor::Dark)
-                && !board::sq_attacked(&board.pos, attack_info, Sq::E1, PieceColor::Dark)
-            {
-                ml.moves
-                    .push(Move::from_str("e1c1", Piece::LK, false, false, false, true));
-            }
-        }
+    if castling.get(CastlingType::WhiteQueenside as usize)
+        && !both_units.get(Sq::B1 as usize)
+        && !both_units.get(Sq::C1 as usize)
+        && !both_units.get(Sq::D1 as usize)
+        && !board::sq_attacked(&board.pos, Sq::D1, PieceColor::Dark)
+        && !board::sq_attacked(&board.pos, Sq::E1, PieceColor::Dark)
+    {
+        ml.moves
+            .push(Move::from_str("e1c1", Piece::LK, false, false, false, true));
     }
 }
 
-fn gen_dark_castling(board: &Board, attack_info: &AttackInfo, ml: &mut MoveList) {
+fn gen_dark_castling(board: &Board, ml: &mut MoveList) {
     let castling = board.state.castling as BB;
     let both_units = board.pos.units(PieceColor::Both);
-    if castling.get(CastlingType::BlackKingside as usize) {
-        if !both_units.get(Sq::F8 as usize) && !both_units.get(Sq::G8 as usize) {
-            if !board::sq_attacked(&board.pos, attack_info, Sq::E8, PieceColor::Light)
-                && !board::sq_attacked(&board.pos, attack_info, Sq::F8, PieceColor::Light)
-            {
-                ml.moves
-                    .push(Move::from_str("e8g8", Piece::DK, false, false, false, true));
-            }
-        }
+    if castling.get(CastlingType::BlackKingside as usize)
+        && !both_units.get(Sq::F8 as usize)
+        && !both_units.get(Sq::G8 as usize)
+        && !board::sq_attacked(&board.pos, Sq::E8, PieceColor::Light)
+        && !board::sq_attacked(&board.pos, Sq::F8, PieceColor::Light)
+    {
+        ml.moves
+            .push(Move::from_str("e8g8", Piece::DK, false, false, false, true));
     }
 
-    if castling.get(CastlingType::BlackQueenside as usize) {
-        if !both_units.get(Sq::B8 as usize)
-            && !both_units.get(Sq::C8 as usize)
-            && !both_units.get(Sq::D8 as usize)
-        {
-            if !board::sq_attacked(&board.pos, attack_info, Sq::D8, PieceColor::Light)
-                && !board::sq_attacked(&board.pos, attack_info, Sq::E8, PieceColor::Light)
-            {
-                ml.moves
-                    .push(Move::from_str("e8c8", Piece::DK, false, false, false, true));
-            }
-        }
+    if castling.get(CastlingType::BlackQueenside as usize)
+        && !both_units.get(Sq::B8 as usize)
+        && !both_units.get(Sq::C8 as usize)
+        && !both_units.get(Sq::D8 as usize)
+        && !board::sq_attacked(&board.pos, Sq::D8, PieceColor::Light)
+        && !board::sq_attacked(&board.pos, Sq::E8, PieceColor::Light)
+    {
+        ml.moves
+            .push(Move::from_str("e8c8", Piece::DK, false, false, false, true));
     }
 }
@@ -1,12 +1,12 @@
-use crate::attack::AttackInfo;
 use crate::bb::BBUtil;
-use crate::board::{self, Board};
+use crate::board::{self, Board, Undo};
 use crate::consts::{Direction, Piece, PieceColor, Sq};
 use crate::zobrist::{self, ZobristAction};
 
 pub type Move = u32;
 
 pub trait MoveUtil {
+    #[allow(clippy::too_many_arguments)]
     fn encode(
         source: Sq,
         target: Sq,
@@ -120,210 +120,240 @@ impl MoveUtil for Move {
     }
 }
 
-#[derive(PartialEq)]
-pub enum MoveFlag {
-    AllMoves,
-    CapturesOnly,
-}
-
 const CASTLING_RIGHTS: [usize; 64] = [
     7, 15, 15, 15, 3, 15, 15, 11, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
     15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
     15, 15, 15, 15, 15, 15, 15, 15, 13, 15, 15, 15, 12, 15, 15, 14,
 ];
 
-pub fn play(
-    main: &mut Board,
-    attack_info: &AttackInfo,
-    mv: Move,
-    move_flag: MoveFlag,
-) -> bool {
-    if move_flag == MoveFlag::AllMoves {
-        let clone = main.clone();
 
+/// Returns the rook type along with its source and target square for a castling move
+fn castling_rook(king_target: Sq) -> (Piece, Sq, Sq) {
+    match king_target {
+        Sq::G1 => (Piece::LR, Sq::H1, Sq::F1),
+        Sq::C1 => (Piece::LR, Sq::A1, Sq::D1),
+        Sq::G8 => (Piece::DR, Sq::H8, Sq::F8),
+        Sq::C8 => (Piece::DR, Sq::A8, Sq::D8),
+        _ => unreachable!("Target castling square should only be [ G1, C1 ] for white and [ G8, C8 ] for black"),
+    }
+}
+
+impl Board {
+    /// Plays a move on the board and returns the record `unmake_move` needs to take it back.
+    /// Returns None (with the board left untouched) if the move leaves the king in check.
+    pub fn make_move(&mut self, mv: Move) -> Option<Undo> {
+        let undo = self.make_move_unchecked(mv);
+        if board::in_check(self, self.state.side) {
+            self.unmake_move(undo);
+            None
+        } else {
+            Some(undo)
+        }
+    }
+
+    /// Plays a move that is already known to be legal (e.g. one from `move_gen::generate_legal`)
+    /// without testing whether it leaves the king in check
+    pub fn make_move_unchecked(&mut self, mv: Move) -> Undo {
         // Extract information about the move
         let source = mv.source() as usize;
         let target = mv.target() as usize;
-        let piece = Piece::to_num(Some(mv.piece()));
+        let piece = mv.piece();
         let promoted = mv.promoted();
-        let is_capture = mv.is_capture();
-        let is_twosquare = mv.is_twosquare();
-        let is_enpassant = mv.is_enpassant();
-        let is_castling = mv.is_castling();
+
+        let mut undo = Undo {
+            mv,
+            captured: None,
+            castling: self.state.castling,
+            enpassant: self.state.enpassant,
+            half_moves: self.state.half_moves,
+            key: self.state.key,
+            lock: self.state.lock,
+        };
+
+        // Pawn moves and captures are irreversible, so they reset the fifty move counter
+        if piece == Piece::LP || piece == Piece::DP || mv.is_capture() {
+            self.state.half_moves = 0;
+        } else {
+            self.state.half_moves += 1;
+        }
 
         // Move piece from source to target by removing source bit and turning on the target bit
-        main.pos.piece[piece].pop(source);
-        main.pos.piece[piece].set(target);
+        self.pos.piece[piece as usize].pop(source);
+        self.pos.piece[piece as usize].set(target);
 
         // Update hash key and lock
-        zobrist::update(
-            ZobristAction::TogglePiece(Piece::from_num(piece).unwrap(), Sq::from_num(source)),
-            main,
-        );
-        zobrist::update(
-            ZobristAction::TogglePiece(Piece::from_num(piece).unwrap(), Sq::from_num(target)),
-            main,
-        );
+        zobrist::update(ZobristAction::TogglePiece(piece, mv.source()), self);
+        zobrist::update(ZobristAction::TogglePiece(piece, mv.target()), self);
 
-        if is_capture {
-            let (start, end) = if main.state.side == PieceColor::Light {
+        if mv.is_capture() {
+            let (start, end) = if self.state.side == PieceColor::Light {
                 (Piece::DP as usize, Piece::DK as usize)
             } else {
                 (Piece::LP as usize, Piece::LK as usize)
             };
             for bb_piece in start..=end {
-                if main.pos.piece[bb_piece].get(target) {
-                    main.pos.piece[bb_piece].pop(target);
-                    zobrist::update(
-                        ZobristAction::TogglePiece(
-                            Piece::from_num(bb_piece).unwrap(),
-                            Sq::from_num(target),
-                        ),
-                        main,
-                    );
+                if self.pos.piece[bb_piece].get(target) {
+                    let captured = Piece::from_num(bb_piece).unwrap();
+                    self.pos.piece[bb_piece].pop(target);
+                    zobrist::update(ZobristAction::TogglePiece(captured, mv.target()), self);
+                    undo.captured = Some(captured);
                     break;
                 }
             }
         }
 
-        if promoted.is_some() {
-            assert!(piece == 0 || piece == 6);
-            let promoted_num = Piece::to_num(promoted);
-            let pawn_type = if piece == 0 { Piece::LP } else { Piece::DP } as usize;
-            main.pos.piece[pawn_type].pop(target);
-            zobrist::update(
-                ZobristAction::TogglePiece(Piece::from_num(pawn_type).unwrap(), Sq::from_num(target)),
-                main,
-            );
+        if let Some(promoted) = promoted {
+            assert!(piece == Piece::LP || piece == Piece::DP);
+            self.pos.piece[piece as usize].pop(target);
+            zobrist::update(ZobristAction::TogglePiece(piece, mv.target()), self);
 
-            main.pos.piece[promoted_num].set(target);
-            zobrist::update(
-                ZobristAction::TogglePiece(
-                    Piece::from_num(promoted_num).unwrap(),
-                    Sq::from_num(target),
-                ),
-                main,
-            );
+            self.pos.piece[promoted as usize].set(target);
+            zobrist::update(ZobristAction::TogglePiece(promoted, mv.target()), self);
         }
 
-        if is_enpassant {
-            let pawn_type;
-            let direction;
-            if main.state.side == PieceColor::Light {
-                pawn_type = Piece::DP;
-                direction = Direction::North;
+        if mv.is_enpassant() {
+            let (pawn_type, direction) = if self.state.side == PieceColor::Light {
+                (Piece::DP, Direction::North)
             } else {
-                pawn_type = Piece::LP;
-                direction = Direction::South;
-            }
-            main.pos.piece[pawn_type as usize].pop((target as i32 + direction as i32) as usize);
+                (Piece::LP, Direction::South)
+            };
+            let captured_sq = (target as i32 + direction as i32) as usize;
+            self.pos.piece[pawn_type as usize].pop(captured_sq);
             zobrist::update(
-                ZobristAction::TogglePiece(pawn_type, Sq::from_num((target as i32 + direction as i32) as usize)),
-                main,
+                ZobristAction::TogglePiece(pawn_type, Sq::from_num(captured_sq)),
+                self,
             );
         }
-        if main.state.enpassant != Sq::NoSq {
-            zobrist::update(
-                ZobristAction::Enpassant,
-                main,
-            );
+        if self.state.enpassant != Sq::NoSq {
+            zobrist::update(ZobristAction::Enpassant, self);
         }
-        main.state.enpassant = Sq::NoSq;
+        self.state.enpassant = Sq::NoSq;
 
-        if is_twosquare {
-            if main.state.side == PieceColor::Light {
-                main.state.enpassant =
-                    Sq::from_num((target as i32 + Direction::North as i32) as usize);
+        if mv.is_twosquare() {
+            let direction = if self.state.side == PieceColor::Light {
+                Direction::North
             } else {
-                main.state.enpassant =
-                    Sq::from_num((target as i32 + Direction::South as i32) as usize);
-            }
-            zobrist::update(
-                ZobristAction::Enpassant,
-                main,
-            );
+                Direction::South
+            };
+            self.state.enpassant = Sq::from_num((target as i32 + direction as i32) as usize);
+            zobrist::update(ZobristAction::Enpassant, self);
         }
 
-        if is_castling {
-            let rook_type;
-            let source_castling;
-            let target_castling;
-            match Sq::from_num(target) {
-                Sq::G1 => {
-                    rook_type = Piece::LR;
-                    source_castling = Sq::H1;
-                    target_castling = Sq::F1;
-                },
-                Sq::C1 => {
-                    rook_type = Piece::LR;
-                    source_castling = Sq::A1;
-                    target_castling = Sq::D1;
-                },
-                Sq::G8 => {
-                    rook_type = Piece::DR;
-                    source_castling = Sq::H8;
-                    target_castling = Sq::F8;
-                },
-                Sq::C8 => {
-                    rook_type = Piece::DR;
-                    source_castling = Sq::A8;
-                    target_castling = Sq::D8;
-                },
-                _ => unreachable!("Target castling square should only be [ G1, C1 ] for white and [ G8, C8 ] for black"),
-            };
-            main.pos.piece[rook_type as usize].pop(source_castling as usize);
-            zobrist::update(
-                ZobristAction::TogglePiece(rook_type, source_castling),
-                main,
-            );
+        if mv.is_castling() {
+            let (rook_type, rook_source, rook_target) = castling_rook(mv.target());
+            self.pos.piece[rook_type as usize].pop(rook_source as usize);
+            zobrist::update(ZobristAction::TogglePiece(rook_type, rook_source), self);
 
-            main.pos.piece[rook_type as usize].set(target_castling as usize);
-            zobrist::update(
-                ZobristAction::TogglePiece(rook_type, target_castling),
-                main,
-            );
+            self.pos.piece[rook_type as usize].set(rook_target as usize);
+            zobrist::update(ZobristAction::TogglePiece(rook_type, rook_target), self);
         }
 
-        zobrist::update(ZobristAction::Castling, main);
-        main.state.castling &= CASTLING_RIGHTS[source] as u8;
-        main.state.castling &= CASTLING_RIGHTS[target] as u8;
-        zobrist::update(ZobristAction::Castling, main);
+        zobrist::update(ZobristAction::Castling, self);
+        self.state.castling &= CASTLING_RIGHTS[source] as u8;
+        self.state.castling &= CASTLING_RIGHTS[target] as u8;
+        zobrist::update(ZobristAction::Castling, self);
 
-        main.state.change_side();
-        zobrist::update(
-            ZobristAction::ChangeColor,
-            main,
-        );
+        self.state.change_side();
+        zobrist::update(ZobristAction::ChangeColor, self);
+        // Increment full moves after black's move
+        if self.state.side == PieceColor::Light {
+            self.state.full_moves += 1;
+        }
 
         /* ============= FOR DEBUG PURPOSES ONLY ===============
-        let key_from_scratch = zobrist::gen_board_key(&zobrist_info.key, &main);
-        let lock_from_scratch = zobrist::gen_board_lock(&zobrist_info.lock, &main);
+        let key_from_scratch = zobrist::gen_board_key(self);
+        let lock_from_scratch = zobrist::gen_board_lock(self);
         assert!(
-            main.state.key == key_from_scratch,
-            "Incorrect key: main.state.key({}), from_scratch({})",
-            main.state.key,
+            self.state.key == key_from_scratch,
+            "Incorrect key: self.state.key({}), from_scratch({})",
+            self.state.key,
             key_from_scratch
         );
         assert!(
-            main.state.lock == lock_from_scratch,
-            "Incorrect lock: main.state.lock({}), from_scratch({})",
-            main.state.lock,
+            self.state.lock == lock_from_scratch,
+            "Incorrect lock: self.state.lock({}), from_scratch({})",
+            self.state.lock,
             lock_from_scratch
         );
          ============= FOR DEBUG PURPOSES ONLY =============== */
-        if board::in_check(main, attack_info, main.state.side) {
-            *main = clone;
-            false
+        undo
+    }
+
+    /// Takes back the move `undo` was returned for. Moves have to be taken back in the
+    /// reverse order they were played in.
+    pub fn unmake_move(&mut self, undo: Undo) {
+        let mv = undo.mv;
+        let source = mv.source() as usize;
+        let target = mv.target() as usize;
+        let piece = mv.piece();
+
+        if self.state.side == PieceColor::Light {
+            self.state.full_moves -= 1;
+        }
+        self.state.change_side();
+
+        // Move the piece (or the promoted piece's pawn) back to its source square
+        if let Some(promoted) = mv.promoted() {
+            self.pos.piece[promoted as usize].pop(target);
         } else {
-            // Increment full moves
-            if main.state.side == PieceColor::Dark {
-                main.state.full_moves += 1;
-            }
-            true
+            self.pos.piece[piece as usize].pop(target);
         }
-    } else if mv.is_capture() {
-        play(main, attack_info, mv, MoveFlag::AllMoves)
-    } else {
-        false
+        self.pos.piece[piece as usize].set(source);
+
+        if let Some(captured) = undo.captured {
+            self.pos.piece[captured as usize].set(target);
+        }
+
+        if mv.is_enpassant() {
+            let (pawn_type, direction) = if self.state.side == PieceColor::Light {
+                (Piece::DP, Direction::North)
+            } else {
+                (Piece::LP, Direction::South)
+            };
+            self.pos.piece[pawn_type as usize].set((target as i32 + direction as i32) as usize);
+        }
+
+        if mv.is_castling() {
+            let (rook_type, rook_source, rook_target) = castling_rook(mv.target());
+            self.pos.piece[rook_type as usize].pop(rook_target as usize);
+            self.pos.piece[rook_type as usize].set(rook_source as usize);
+        }
+
+        self.state.castling = undo.castling;
+        self.state.enpassant = undo.enpassant;
+        self.state.half_moves = undo.half_moves;
+        self.state.key = undo.key;
+        self.state.lock = undo.lock;
+    }
+
+    /// Passes the turn to the opponent without moving a piece (used by null move pruning)
+    pub fn make_null_move(&mut self) -> Undo {
+        let undo = Undo {
+            mv: 0,
+            captured: None,
+            castling: self.state.castling,
+            enpassant: self.state.enpassant,
+            half_moves: self.state.half_moves,
+            key: self.state.key,
+            lock: self.state.lock,
+        };
+        if self.state.enpassant != Sq::NoSq {
+            zobrist::update(ZobristAction::Enpassant, self);
+        }
+        self.state.enpassant = Sq::NoSq;
+        // Positions before a null move can't be repeated through it
+        self.state.half_moves = 0;
+        self.state.change_side();
+        zobrist::update(ZobristAction::ChangeColor, self);
+        undo
+    }
+
+    /// Takes back the null move `undo` was returned for
+    pub fn unmake_null_move(&mut self, undo: Undo) {
+        self.state.change_side();
+        self.state.enpassant = undo.enpassant;
+        self.state.half_moves = undo.half_moves;
+        self.state.key = undo.key;
+        self.state.lock = undo.lock;
     }
 }
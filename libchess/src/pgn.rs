@@ -0,0 +1,543 @@
+use crate::board::Board;
+use crate::consts::PieceColor;
+use crate::fen::{FenError, FEN_POSITIONS};
+use crate::moves::Move;
+use crate::san::SanError;
+
+use std::fmt;
+use std::io::{self, BufRead};
+
+const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+// Export format lines shouldn't be longer than this
+const MAX_LINE_LEN: usize = 80;
+
+#[derive(Debug)]
+pub enum PgnError {
+    Io(io::Error),
+    InvalidTag(String),
+    InvalidFen(FenError),
+    InvalidMove(SanError),
+    UnexpectedToken(String),
+    UnterminatedComment,
+    UnterminatedVariation,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "couldn't read PGN: {}", e),
+            Self::InvalidTag(tag) => write!(f, "invalid tag pair '{}'", tag),
+            Self::InvalidFen(e) => write!(f, "invalid FEN tag: {}", e),
+            Self::InvalidMove(e) => write!(f, "invalid move: {}", e),
+            Self::UnexpectedToken(token) => write!(f, "unexpected '{}' in movetext", token),
+            Self::UnterminatedComment => write!(f, "comment is missing its closing '}}'"),
+            Self::UnterminatedVariation => write!(f, "variation is missing its closing ')'"),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+impl From<io::Error> for PgnError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<FenError> for PgnError {
+    fn from(e: FenError) -> Self {
+        Self::InvalidFen(e)
+    }
+}
+
+impl From<SanError> for PgnError {
+    fn from(e: SanError) -> Self {
+        Self::InvalidMove(e)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct PgnMove {
+    pub mv: Move,
+    // Numeric annotation glyphs; suffixes like '!?' are stored as their NAG
+    pub nags: Vec<u8>,
+    pub comment_before: Option<String>,
+    pub comment: Option<String>,
+    // Alternatives to this move, each played from the position before it
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    pub fn new(mv: Move) -> Self {
+        Self {
+            mv,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Game {
+    // Tag pairs in the order they were read or added
+    pub tags: Vec<(String, String)>,
+    pub start: Board,
+    pub moves: Vec<PgnMove>,
+    pub result: String,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Game {
+    pub fn new() -> Self {
+        Self {
+            tags: vec![],
+            start: Board::from_fen(FEN_POSITIONS[1]).unwrap(),
+            moves: vec![],
+            result: String::from("*"),
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag_name, _)| tag_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        if let Some(tag) = self.tags.iter_mut().find(|(tag_name, _)| tag_name == name) {
+            tag.1 = value.to_string();
+        } else {
+            self.tags.push((name.to_string(), value.to_string()));
+        }
+    }
+
+    pub fn mainline(&self) -> impl Iterator<Item = Move> + '_ {
+        self.moves.iter().map(|node| node.mv)
+    }
+
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+
+        // The seven tag roster always comes first and in this order
+        for name in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Result" => self.result.as_str(),
+                _ => self.tag(name).unwrap_or("?"),
+            };
+            pgn.push_str(&format_tag(name, value));
+        }
+        // The start position always comes from `start`, so the game can be replayed
+        // whatever the tags say
+        let fen = self.start.to_fen();
+        if fen != FEN_POSITIONS[1] {
+            pgn.push_str(&format_tag("SetUp", "1"));
+            pgn.push_str(&format_tag("FEN", &fen));
+        }
+        for (name, value) in &self.tags {
+            if !SEVEN_TAG_ROSTER.contains(&name.as_str()) && name != "SetUp" && name != "FEN" {
+                pgn.push_str(&format_tag(name, value));
+            }
+        }
+        pgn.push('\n');
+
+        let mut tokens = vec![];
+        write_line(&self.moves, self.start, &mut tokens);
+        tokens.push(self.result.clone());
+
+        // Wrap the movetext
+        let mut line_len = 0;
+        for token in tokens {
+            if line_len > 0 && line_len + 1 + token.len() > MAX_LINE_LEN {
+                pgn.push('\n');
+                line_len = 0;
+            } else if line_len > 0 {
+                pgn.push(' ');
+                line_len += 1;
+            }
+            line_len += token.len();
+            pgn.push_str(&token);
+        }
+        pgn.push_str("\n\n");
+        pgn
+    }
+}
+
+fn format_tag(name: &str, value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("[{} \"{}\"]\n", name, escaped)
+}
+
+fn write_line(line: &[PgnMove], mut board: Board, tokens: &mut Vec<String>) {
+    // Black's moves only get a move number at the start of a line or after an interruption
+    let mut need_number = true;
+    for node in line {
+        if let Some(comment) = &node.comment_before {
+            tokens.push(format!("{{{}}}", comment));
+            need_number = true;
+        }
+        if board.state.side == PieceColor::Light {
+            tokens.push(format!("{}.", board.state.full_moves));
+        } else if need_number {
+            tokens.push(format!("{}...", board.state.full_moves));
+        }
+        tokens.push(board.move_to_san(node.mv));
+        need_number = false;
+
+        for nag in &node.nags {
+            tokens.push(format!("${}", nag));
+        }
+        if let Some(comment) = &node.comment {
+            tokens.push(format!("{{{}}}", comment));
+            need_number = true;
+        }
+        for variation in node.variations.iter().filter(|v| !v.is_empty()) {
+            let start = tokens.len();
+            write_line(variation, board, tokens);
+            tokens[start].insert(0, '(');
+            tokens.last_mut().unwrap().push(')');
+            need_number = true;
+        }
+        board.make_move_unchecked(node.mv);
+    }
+}
+
+/// Reads games one at a time from a (possibly multi-game) PGN source
+pub struct PgnReader<R: BufRead> {
+    reader: R,
+    // First line of the next game, read while looking for the end of the current one
+    next_line: Option<String>,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            next_line: None,
+        }
+    }
+
+    // Collects the tag pairs and the movetext of the next game
+    fn read_game_text(&mut self) -> Result<Option<(Vec<String>, String)>, PgnError> {
+        let mut tags = vec![];
+        let mut movetext = String::new();
+        let mut in_comment = false;
+        let mut variation_depth = 0;
+        loop {
+            let line = match self.next_line.take() {
+                Some(line) => line,
+                None => {
+                    let mut line = String::new();
+                    if self.reader.read_line(&mut line)? == 0 {
+                        break;
+                    }
+                    line
+                }
+            };
+            let trimmed = line.trim();
+            if !in_comment && trimmed.starts_with('[') {
+                // A tag pair after the movetext belongs to the next game
+                if !movetext.trim().is_empty() {
+                    self.next_line = Some(line);
+                    break;
+                }
+                tags.push(trimmed.to_string());
+                continue;
+            }
+            // Escape mechanism; the whole line is ignored
+            if !in_comment && trimmed.starts_with('%') {
+                continue;
+            }
+            // The result ends the game, even if no tag pairs follow it
+            if let Some(end) = find_result_end(trimmed, &mut in_comment, &mut variation_depth) {
+                movetext.push_str(&trimmed[..end]);
+                movetext.push('\n');
+                let rest = trimmed[end..].trim();
+                if !rest.is_empty() {
+                    self.next_line = Some(rest.to_string());
+                }
+                break;
+            }
+            movetext.push_str(trimmed);
+            movetext.push('\n');
+        }
+        if tags.is_empty() && movetext.trim().is_empty() {
+            return Ok(None);
+        }
+        Ok(Some((tags, movetext)))
+    }
+
+    fn parse_game(&self, tags: Vec<String>, movetext: &str) -> Result<Game, PgnError> {
+        let mut game = Game::new();
+        for tag in tags {
+            let (name, value) = parse_tag(&tag).ok_or(PgnError::InvalidTag(tag.clone()))?;
+            game.set_tag(&name, &value);
+        }
+        if let Some(fen) = game.tag("FEN") {
+            game.start = Board::from_fen(fen)?;
+        }
+        if let Some(result) = game.tag("Result") {
+            game.result = result.to_string();
+        }
+
+        let tokens = tokenize(movetext)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            result: None,
+        };
+        game.moves = parser.parse_line(game.start, false)?;
+        if let Some(result) = parser.result {
+            game.result = result;
+        }
+        Ok(game)
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<Game, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_game_text() {
+            Ok(Some((tags, movetext))) => Some(self.parse_game(tags, &movetext)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+// Scans a line of movetext for a game termination marker outside of comments and
+// variations and returns where it ends. Comments and variations may carry over from
+// earlier lines, so their state is kept by the caller.
+fn find_result_end(line: &str, in_comment: &mut bool, variation_depth: &mut u32) -> Option<usize> {
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '}' if *in_comment => *in_comment = false,
+            _ if *in_comment => {}
+            '{' => *in_comment = true,
+            // The rest of the line is a comment
+            ';' => return None,
+            '(' => *variation_depth += 1,
+            ')' => *variation_depth = variation_depth.saturating_sub(1),
+            _ if c.is_whitespace() => {}
+            _ => {
+                let mut end = i + c.len_utf8();
+                while let Some(&(j, c)) = chars.peek() {
+                    if c.is_whitespace() || "{};()".contains(c) {
+                        break;
+                    }
+                    end = j + c.len_utf8();
+                    chars.next();
+                }
+                if *variation_depth == 0 && RESULTS.contains(&&line[i..end]) {
+                    return Some(end);
+                }
+            }
+        }
+    }
+    None
+}
+
+fn parse_tag(tag: &str) -> Option<(String, String)> {
+    let inner = tag.strip_prefix('[')?.strip_suffix(']')?.trim();
+    let (name, rest) = inner.split_once(char::is_whitespace)?;
+    let quoted = rest.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let mut value = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            value.push(chars.next()?);
+        } else {
+            value.push(c);
+        }
+    }
+    Some((name.to_string(), value))
+}
+
+enum Token {
+    Comment(String),
+    Nag(u8),
+    OpenVariation,
+    CloseVariation,
+    Result(String),
+    San(String),
+}
+
+// Move suffix annotations and their equivalent NAG
+const SUFFIX_NAGS: [(&str, u8); 6] = [("!", 1), ("?", 2), ("!!", 3), ("??", 4), ("!?", 5), ("?!", 6)];
+
+fn tokenize(movetext: &str) -> Result<Vec<Token>, PgnError> {
+    let mut tokens = vec![];
+    let mut chars = movetext.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+            }
+            '{' => {
+                chars.next();
+                let mut comment = String::new();
+                let mut is_closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        is_closed = true;
+                        break;
+                    }
+                    comment.push(c);
+                }
+                if !is_closed {
+                    return Err(PgnError::UnterminatedComment);
+                }
+                tokens.push(Token::Comment(comment.split_whitespace().collect::<Vec<_>>().join(" ")));
+            }
+            ';' => {
+                chars.next();
+                let comment: String = chars.by_ref().take_while(|&c| c != '\n').collect();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::OpenVariation);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::CloseVariation);
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "{};()".contains(c) || (c == '$' && !word.is_empty()) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokenize_word(&word, &mut tokens)?;
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn tokenize_word(word: &str, tokens: &mut Vec<Token>) -> Result<(), PgnError> {
+    if RESULTS.contains(&word) {
+        tokens.push(Token::Result(word.to_string()));
+        return Ok(());
+    }
+    if let Some(nag) = word.strip_prefix('$') {
+        let nag = nag
+            .parse()
+            .map_err(|_| PgnError::UnexpectedToken(word.to_string()))?;
+        tokens.push(Token::Nag(nag));
+        return Ok(());
+    }
+
+    // Move numbers ('12.', '12...') may be glued to the move itself
+    let digits = word.len() - word.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let san = if digits > 0 && word[digits..].starts_with('.') {
+        word[digits..].trim_start_matches('.')
+    } else {
+        word
+    };
+    if san.is_empty() {
+        return Ok(());
+    }
+
+    let move_part = san.trim_end_matches(['!', '?']);
+    tokens.push(Token::San(move_part.to_string()));
+    let suffix = &san[move_part.len()..];
+    if !suffix.is_empty() {
+        let (_, nag) = SUFFIX_NAGS
+            .iter()
+            .find(|(s, _)| *s == suffix)
+            .ok_or(PgnError::UnexpectedToken(word.to_string()))?;
+        tokens.push(Token::Nag(*nag));
+    }
+    Ok(())
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    result: Option<String>,
+}
+
+impl Parser {
+    // Parses moves until the end of the current line, starting from `board`
+    fn parse_line(&mut self, mut board: Board, is_variation: bool) -> Result<Vec<PgnMove>, PgnError> {
+        let mut line: Vec<PgnMove> = vec![];
+        // The position before the last move, where its variations start from
+        let mut previous = board;
+        let mut pending_comment: Option<String> = None;
+        loop {
+            let Some(token) = self.tokens.get(self.pos) else {
+                if is_variation {
+                    return Err(PgnError::UnterminatedVariation);
+                }
+                break;
+            };
+            self.pos += 1;
+            match token {
+                Token::Comment(comment) => {
+                    let target = match line.last_mut() {
+                        Some(node) => &mut node.comment,
+                        None => &mut pending_comment,
+                    };
+                    append_comment(target, comment);
+                }
+                Token::Nag(nag) => {
+                    let nag = *nag;
+                    line.last_mut()
+                        .ok_or(PgnError::UnexpectedToken(format!("${}", nag)))?
+                        .nags
+                        .push(nag);
+                }
+                Token::OpenVariation => {
+                    if line.is_empty() {
+                        return Err(PgnError::UnexpectedToken(String::from("(")));
+                    }
+                    let variation = self.parse_line(previous, true)?;
+                    line.last_mut().unwrap().variations.push(variation);
+                }
+                Token::CloseVariation => {
+                    if !is_variation {
+                        return Err(PgnError::UnexpectedToken(String::from(")")));
+                    }
+                    break;
+                }
+                Token::Result(result) => {
+                    if is_variation {
+                        return Err(PgnError::UnexpectedToken(result.clone()));
+                    }
+                    self.result = Some(result.clone());
+                    break;
+                }
+                Token::San(san) => {
+                    let mv = board.parse_san(san)?;
+                    previous = board;
+                    board.make_move_unchecked(mv);
+                    let mut node = PgnMove::new(mv);
+                    node.comment_before = pending_comment.take();
+                    line.push(node);
+                }
+            }
+        }
+        Ok(line)
+    }
+}
+
+fn append_comment(target: &mut Option<String>, comment: &str) {
+    match target {
+        Some(existing) => {
+            existing.push(' ');
+            existing.push_str(comment);
+        }
+        None => *target = Some(comment.to_string()),
+    }
+}
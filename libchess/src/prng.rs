@@ -1,14 +1,15 @@
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Clone)]
 pub struct PRNG {
     seed: u128
 }
 
 impl PRNG {
-    pub fn new(initial_seed: u128) -> Self {
+    pub const fn new(initial_seed: u128) -> Self {
         Self { seed: initial_seed }
     }
 
-    pub fn rand64(self: &mut Self) -> u64 {
+    pub const fn rand64(&mut self) -> u64 {
         let mut x = self.seed;
         x ^= x >> 12;
         x ^= x << 25;
@@ -19,7 +20,7 @@ impl PRNG {
         r ^ ((x >> 64) as u64)
     }
 
-    pub fn sparse_rand64(self: &mut Self) -> u64 {
+    pub fn sparse_rand64(&mut self) -> u64 {
         self.rand64() & self.rand64() & self.rand64()
     }
 }
@@ -0,0 +1,183 @@
+use crate::board::{self, Board};
+use crate::consts::{Piece, PieceType, Sq};
+use crate::move_gen::{self, MoveList};
+use crate::moves::{Move, MoveUtil};
+use crate::{COL, ROW};
+
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum SanError {
+    InvalidSyntax(String),
+    IllegalMove(String),
+    AmbiguousMove(String),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSyntax(san) => write!(f, "'{}' isn't a valid SAN move", san),
+            Self::IllegalMove(san) => write!(f, "'{}' isn't a legal move", san),
+            Self::AmbiguousMove(san) => write!(f, "'{}' matches more than one move", san),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+fn legal_moves(board: &Board) -> Vec<Move> {
+    let mut ml = MoveList::new();
+    move_gen::generate_legal(board, &mut ml);
+    ml.moves
+}
+
+fn piece_type(piece: Piece) -> usize {
+    piece as usize % 6
+}
+
+// SAN always uses the uppercase letter regardless of the side
+fn piece_letter(piece_type: usize) -> char {
+    Piece::to_char(Piece::from_num(piece_type))
+}
+
+pub fn to_san(board: &Board, mv: Move) -> String {
+    let mut san = String::new();
+    let source = mv.source() as usize;
+    let target = mv.target() as usize;
+    let moving_type = piece_type(mv.piece());
+
+    if mv.is_castling() {
+        san.push_str(if COL!(target) == 6 { "O-O" } else { "O-O-O" });
+    } else {
+        if moving_type == PieceType::Pawn as usize {
+            if mv.is_capture() {
+                san.push((b'a' + COL!(source) as u8) as char);
+            }
+        } else {
+            san.push(piece_letter(moving_type));
+
+            // Disambiguate between pieces of the same type that can go to the same square
+            let others: Vec<usize> = legal_moves(board)
+                .into_iter()
+                .filter(|other| {
+                    piece_type(other.piece()) == moving_type
+                        && other.target() == mv.target()
+                        && other.source() != mv.source()
+                })
+                .map(|other| other.source() as usize)
+                .collect();
+            if !others.is_empty() {
+                let same_file = others.iter().any(|sq| COL!(sq) == COL!(source));
+                let same_rank = others.iter().any(|sq| ROW!(sq) == ROW!(source));
+                let sq_str = Sq::to_string(mv.source());
+                if !same_file {
+                    san.push_str(&sq_str[0..1]);
+                } else if !same_rank {
+                    san.push_str(&sq_str[1..2]);
+                } else {
+                    san.push_str(&sq_str);
+                }
+            }
+        }
+        if mv.is_capture() {
+            san.push('x');
+        }
+        san.push_str(&Sq::to_string(mv.target()));
+        if let Some(promoted) = mv.promoted() {
+            san.push('=');
+            san.push(piece_letter(piece_type(promoted)));
+        }
+    }
+
+    // Check and checkmate suffixes
+    let mut after = *board;
+    after.make_move_unchecked(mv);
+    if board::in_check(&after, after.state.xside) {
+        san.push(if legal_moves(&after).is_empty() {
+            '#'
+        } else {
+            '+'
+        });
+    }
+    san
+}
+
+// Accepts the common variations of SAN as well: '0-0' for castling, missing or extra
+// check/annotation suffixes, promotions without '=' ('e8Q') and an optional '-' between squares
+pub fn parse(board: &Board, san: &str) -> Result<Move, SanError> {
+    let syntax_err = || SanError::InvalidSyntax(san.to_string());
+    let trimmed = san.trim().trim_end_matches(['+', '#', '!', '?']);
+    let legal = legal_moves(board);
+
+    let candidates: Vec<Move> = match trimmed {
+        "O-O" | "0-0" | "O-O-O" | "0-0-0" => {
+            let target_col = if trimmed.len() == 3 { 6 } else { 2 };
+            legal
+                .into_iter()
+                .filter(|mv| mv.is_castling() && COL!(mv.target() as usize) == target_col)
+                .collect()
+        }
+        _ => {
+            let mut chars: Vec<char> = trimmed.chars().collect();
+
+            // Promotion piece, with or without the '='
+            let mut promoted_type = None;
+            if chars.len() > 2 {
+                let last = chars[chars.len() - 1].to_ascii_uppercase();
+                if "QRBN".contains(last) {
+                    promoted_type = Some(piece_type(Piece::from_char(last).unwrap()));
+                    chars.pop();
+                    if chars.last() == Some(&'=') {
+                        chars.pop();
+                    }
+                }
+            }
+
+            // Target square
+            if chars.len() < 2 {
+                return Err(syntax_err());
+            }
+            let target_str: String = chars.split_off(chars.len() - 2).into_iter().collect();
+            let target = Sq::parse(&target_str).ok_or_else(syntax_err)?;
+
+            // Moving piece type; pawn moves don't have a letter
+            let mut moving_type = PieceType::Pawn as usize;
+            if let Some(first) = chars.first()
+                && "NBRQK".contains(*first)
+            {
+                moving_type = piece_type(Piece::from_char(*first).unwrap());
+                chars.remove(0);
+            }
+
+            // Whatever is left can only be the disambiguation and the capture sign
+            let mut source_col = None;
+            let mut source_row = None;
+            for c in chars {
+                match c {
+                    'a'..='h' => source_col = Some((c as u8 - b'a') as usize),
+                    '1'..='8' => source_row = Some((b'8' - c as u8) as usize),
+                    'x' | ':' | '-' => {}
+                    _ => return Err(syntax_err()),
+                }
+            }
+
+            legal
+                .into_iter()
+                .filter(|mv| {
+                    let source = mv.source() as usize;
+                    piece_type(mv.piece()) == moving_type
+                        && mv.target() == target
+                        && mv.promoted().map(piece_type) == promoted_type
+                        && source_col.is_none_or(|col| COL!(source) == col)
+                        && source_row.is_none_or(|row| ROW!(source) == row)
+                })
+                .collect()
+        }
+    };
+
+    match candidates.len() {
+        0 => Err(SanError::IllegalMove(san.to_string())),
+        1 => Ok(candidates[0]),
+        _ => Err(SanError::AmbiguousMove(san.to_string())),
+    }
+}
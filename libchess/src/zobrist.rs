@@ -3,7 +3,6 @@ use crate::board::Board;
 use crate::consts::{Piece, PieceColor, Sq};
 use crate::prng::PRNG;
 
-#[derive(Clone)]
 pub struct ZobristKey {
     pub piece: [[u64; 64]; 12],
     // Unnecessary work is being done for the enpassant keys
@@ -13,18 +12,6 @@ pub struct ZobristKey {
     pub side: u64,
 }
 
-impl ZobristKey {
-    fn new() -> Self {
-        Self {
-            piece: [[0; 64]; 12],
-            enpassant: [0; 64],
-            castling: [0; 16],
-            side: 0,
-        }
-    }
-}
-
-#[derive(Clone)]
 pub struct ZobristLock {
     pub piece: [[u64; 64]; 12],
     // Unnecessary work is being done for the enpassant keys
@@ -34,54 +21,56 @@ pub struct ZobristLock {
     pub side: u64,
 }
 
-impl ZobristLock {
-    fn new() -> Self {
-        Self {
-            piece: [[0; 64]; 12],
-            enpassant: [0; 64],
-            castling: [0; 16],
-            side: 0,
-        }
-    }
-}
+// The keys are generated at compile time from a fixed seed, so every board,
+// thread and engine run hashes a position the same way
+const TABLES: (ZobristKey, ZobristLock) = gen_tables();
+pub static KEY: ZobristKey = TABLES.0;
+pub static LOCK: ZobristLock = TABLES.1;
 
-#[derive(Clone)]
-pub struct ZobristInfo {
-    pub key: ZobristKey,
-    pub lock: ZobristLock,
-    pub prng: PRNG,
-}
+const fn gen_tables() -> (ZobristKey, ZobristLock) {
+    let mut prng = PRNG::new(0x246C_CB2D_3B40_2853_9918_0A6D_BC3A_F444);
+    let mut key = ZobristKey {
+        piece: [[0; 64]; 12],
+        enpassant: [0; 64],
+        castling: [0; 16],
+        side: 0,
+    };
+    let mut lock = ZobristLock {
+        piece: [[0; 64]; 12],
+        enpassant: [0; 64],
+        castling: [0; 16],
+        side: 0,
+    };
 
-impl ZobristInfo {
-    pub fn new() -> Self {
-        Self {
-            key: ZobristKey::new(),
-            lock: ZobristLock::new(),
-            prng: PRNG::new(0x246C_CB2D_3B40_2853_9918_0A6D_BC3A_F444),
+    // `for` loops aren't allowed in a const fn
+    let mut piece = 0;
+    while piece < 12 {
+        let mut sq = 0;
+        while sq < 64 {
+            key.piece[piece][sq] = prng.rand64();
+            lock.piece[piece][sq] = prng.rand64();
+            sq += 1;
         }
+        piece += 1;
     }
 
-    pub fn init(&mut self) {
-        for piece in 0..=11 {
-            for sq in 0..64 {
-                self.key.piece[piece][sq] = self.prng.rand64();
-                self.lock.piece[piece][sq] = self.prng.rand64();
-            }
-        }
-
-        for sq in 0..64 {
-            self.key.enpassant[sq] = self.prng.rand64();
-            self.lock.enpassant[sq] = self.prng.rand64();
-        }
+    let mut sq = 0;
+    while sq < 64 {
+        key.enpassant[sq] = prng.rand64();
+        lock.enpassant[sq] = prng.rand64();
+        sq += 1;
+    }
 
-        // All different variations of castling rights - (1 << 4)
-        for i in 0..16 {
-            self.key.castling[i] = self.prng.rand64();
-            self.lock.castling[i] = self.prng.rand64();
-        }
-        self.key.side = self.prng.rand64();
-        self.lock.side = self.prng.rand64();
+    // All different variations of castling rights - (1 << 4)
+    let mut i = 0;
+    while i < 16 {
+        key.castling[i] = prng.rand64();
+        lock.castling[i] = prng.rand64();
+        i += 1;
     }
+    key.side = prng.rand64();
+    lock.side = prng.rand64();
+    (key, lock)
 }
 
 pub enum ZobristAction {
@@ -92,30 +81,30 @@ pub enum ZobristAction {
 }
 
 pub fn update(action: ZobristAction, board: &mut Board) {
-    let info = &board.zobrist_info;
     match action {
         ZobristAction::Castling => {
-            board.state.key ^= info.key.castling[board.state.castling as usize];
-            board.state.lock ^= info.lock.castling[board.state.castling as usize];
+            board.state.key ^= KEY.castling[board.state.castling as usize];
+            board.state.lock ^= LOCK.castling[board.state.castling as usize];
         }
         ZobristAction::ChangeColor => {
-            board.state.key ^= info.key.side;
-            board.state.lock ^= info.lock.side;
+            board.state.key ^= KEY.side;
+            board.state.lock ^= LOCK.side;
         }
         ZobristAction::Enpassant => {
             if board.state.enpassant != Sq::NoSq {
-                board.state.key ^= info.key.enpassant[board.state.enpassant as usize];
-                board.state.lock ^= info.lock.enpassant[board.state.enpassant as usize];
+                board.state.key ^= KEY.enpassant[board.state.enpassant as usize];
+                board.state.lock ^= LOCK.enpassant[board.state.enpassant as usize];
             }
         }
         ZobristAction::TogglePiece(piece, sq) => {
-            board.state.key ^= info.key.piece[piece as usize][sq as usize];
-            board.state.lock ^= info.lock.piece[piece as usize][sq as usize];
+            board.state.key ^= KEY.piece[piece as usize][sq as usize];
+            board.state.lock ^= LOCK.piece[piece as usize][sq as usize];
         }
     };
 }
 
-pub fn gen_board_key(key: &ZobristKey, board: &Board) -> u64 {
+pub fn gen_board_key(board: &Board) -> u64 {
+    let key = &KEY;
     let mut final_key = 0;
     let mut bb_copy;
     for piece in 0..12 {
@@ -138,7 +127,8 @@ pub fn gen_board_key(key: &ZobristKey, board: &Board) -> u64 {
     final_key
 }
 
-pub fn gen_board_lock(lock: &ZobristLock, board: &Board) -> u64 {
+pub fn gen_board_lock(board: &Board) -> u64 {
+    let lock = &LOCK;
     let mut final_lock = 0;
     let mut bb_copy;
     for piece in 0..12 {
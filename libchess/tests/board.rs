@@ -0,0 +1,93 @@
+use chess::board::{Board, Undo};
+use chess::fen::FEN_POSITIONS;
+
+const KINGS_ONLY: &str = "7k/8/8/8/8/8/8/K7 w - - 0 1";
+
+// Plays the moves and records their undo records, the way the search and the game do
+fn play(board: &mut Board, history: &mut Vec<Undo>, moves: &[&str]) {
+    for san in moves {
+        let mv = board.parse_san(san).unwrap();
+        history.push(board.make_move(mv).unwrap());
+    }
+}
+
+#[test]
+fn twofold_repetition_inside_the_search() {
+    let mut board = Board::from_fen(FEN_POSITIONS[1]).unwrap();
+    let mut history = vec![];
+    play(&mut board, &mut history, &["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6"]);
+    // The position after 1. Nf3 Nf6 came up 4 plies ago, after the search root
+    assert!(board.is_repetition(&history, 6));
+    // With the earlier occurrence at or before the root it takes a third one
+    assert!(!board.is_repetition(&history, 4));
+    assert!(!board.is_repetition(&history, 0));
+}
+
+#[test]
+fn threefold_repetition_from_game_history() {
+    let mut board = Board::from_fen(FEN_POSITIONS[1]).unwrap();
+    let mut history = vec![];
+    play(&mut board, &mut history, &["Nf3", "Nf6", "Ng1", "Ng8"]);
+    assert!(!board.is_repetition(&history, 0));
+    play(&mut board, &mut history, &["Nf3", "Nf6", "Ng1", "Ng8"]);
+    assert!(board.is_repetition(&history, 0));
+}
+
+#[test]
+fn pawn_moves_and_captures_reset_the_fifty_move_counter() {
+    let mut board = Board::from_fen(FEN_POSITIONS[1]).unwrap();
+    let mut history = vec![];
+    play(&mut board, &mut history, &["Nf3", "Nf6"]);
+    assert_eq!(board.state.half_moves, 2);
+    play(&mut board, &mut history, &["e4"]);
+    assert_eq!(board.state.half_moves, 0);
+    play(&mut board, &mut history, &["Nc6"]);
+    assert_eq!(board.state.half_moves, 1);
+    play(&mut board, &mut history, &["Nc3", "Nxe4"]);
+    assert_eq!(board.state.half_moves, 0);
+    play(&mut board, &mut history, &["Nxe4"]);
+    assert_eq!(board.state.half_moves, 0);
+    // Taking a move back restores the counter
+    board.unmake_move(history.pop().unwrap());
+    assert_eq!(board.state.half_moves, 0);
+    board.unmake_move(history.pop().unwrap());
+    assert_eq!(board.state.half_moves, 2);
+}
+
+#[test]
+fn null_move_is_a_repetition_barrier() {
+    // The white king triangulates so the same position comes back with the same side to move
+    let mut board = Board::from_fen(KINGS_ONLY).unwrap();
+    let mut history = vec![];
+    play(&mut board, &mut history, &["Kb1", "Kg8", "Ka1", "Kh8"]);
+    play(&mut board, &mut history, &["Kb1", "Kg8", "Ka1", "Kh8"]);
+    assert!(board.is_repetition(&history, 0));
+
+    let mut board = Board::from_fen(KINGS_ONLY).unwrap();
+    let mut history = vec![];
+    play(&mut board, &mut history, &["Kb1", "Kg8", "Ka1", "Kh8", "Kb1", "Kg8"]);
+    history.push(board.make_null_move());
+    play(&mut board, &mut history, &["Kh8", "Kb2", "Kg8", "Ka1", "Kh8"]);
+    // Third time the starting position, but the earlier two are behind the null move
+    assert_eq!(board.state.key, Board::from_fen(KINGS_ONLY).unwrap().state.key);
+    assert!(!board.is_repetition(&history, 0));
+    assert!(!board.is_repetition(&history, history.len()));
+}
+
+#[test]
+fn fifty_move_draw_at_a_hundred_half_moves() {
+    let mut board = Board::from_fen("7k/8/8/8/8/8/P7/K7 w - - 98 80").unwrap();
+    let mut history = vec![];
+    play(&mut board, &mut history, &["Kb1"]);
+    assert!(!board.is_fifty_move_draw());
+    play(&mut board, &mut history, &["Kg8"]);
+    assert_eq!(board.state.half_moves, 100);
+    assert!(board.is_fifty_move_draw());
+
+    // A pawn move on the hundredth half move still resets the counter
+    let mut board = Board::from_fen("7k/8/8/8/8/8/P7/K7 b - - 98 80").unwrap();
+    let mut history = vec![];
+    play(&mut board, &mut history, &["Kg8", "a4"]);
+    assert_eq!(board.state.half_moves, 0);
+    assert!(!board.is_fifty_move_draw());
+}
@@ -0,0 +1,128 @@
+use chess::board::Board;
+use chess::consts::Sq;
+use chess::fen::{self, FenError, FEN_POSITIONS};
+use chess::move_gen::{self, MoveList};
+
+#[test]
+fn round_trip_fen_positions() {
+    for fen_str in FEN_POSITIONS {
+        let mut board = Board::new();
+        fen::parse(fen_str, &mut board).unwrap();
+        assert_eq!(board.to_fen(), fen_str);
+    }
+}
+
+#[test]
+fn round_trip_after_moves() {
+    for fen_str in FEN_POSITIONS {
+        let mut board = Board::new();
+        fen::parse(fen_str, &mut board).unwrap();
+        let mut ml = MoveList::new();
+        move_gen::generate(&board, &mut ml);
+        for mv in ml.moves {
+            let Some(undo) = board.make_move(mv) else {
+                continue;
+            };
+            let played = board.to_fen();
+            let mut reparsed = Board::new();
+            fen::parse(&played, &mut reparsed).unwrap();
+            assert_eq!(reparsed.to_fen(), played);
+            assert_eq!(reparsed.state.key, board.state.key);
+            assert_eq!(reparsed.state.lock, board.state.lock);
+            board.unmake_move(undo);
+            assert_eq!(board.to_fen(), fen_str);
+        }
+    }
+}
+
+#[test]
+fn full_moves_count_black_moves() {
+    let mut board = Board::new();
+    fen::parse(FEN_POSITIONS[1], &mut board).unwrap();
+    let mut fens = vec![board.to_fen()];
+    let mut full_moves = vec![board.state.full_moves];
+    let mut undos = vec![];
+    for san in ["e4", "e5", "Nf3", "Nc6"] {
+        let mv = board.parse_san(san).unwrap();
+        undos.push(board.make_move(mv).unwrap());
+        fens.push(board.to_fen());
+        full_moves.push(board.state.full_moves);
+    }
+    // Only black's replies start a new move
+    assert_eq!(full_moves, [1, 1, 2, 2, 3]);
+    assert!(fens[2].ends_with(" 0 2"));
+    while let Some(undo) = undos.pop() {
+        fens.pop();
+        board.unmake_move(undo);
+        assert_eq!(board.to_fen(), *fens.last().unwrap());
+    }
+}
+
+#[test]
+fn invalid_fens_are_rejected() {
+    let cases = [
+        ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR", FenError::MissingField("side")),
+        ("rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::InvalidPiece('x')),
+        (
+            "rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            FenError::InvalidRankLength(7),
+        ),
+        ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNRR w KQkq - 0 1", FenError::InvalidRankLength(1)),
+        ("rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::InvalidRankCount(7)),
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+            FenError::InvalidSide("x".to_string()),
+        ),
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1",
+            FenError::InvalidCastling("KQkx".to_string()),
+        ),
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KKq - 0 1",
+            FenError::InvalidCastling("KKq".to_string()),
+        ),
+        // No rook to castle with
+        ("4k3/8/8/8/8/8/8/4K3 w K - 0 1", FenError::InvalidCastling("K".to_string())),
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1",
+            FenError::InvalidEnpassant("e9".to_string()),
+        ),
+        (
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e4 0 1",
+            FenError::InvalidEnpassant("e4".to_string()),
+        ),
+        // No pawn that just moved past the square
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e6 0 1",
+            FenError::InvalidEnpassant("e6".to_string()),
+        ),
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1",
+            FenError::InvalidHalfMoves("x".to_string()),
+        ),
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 -1",
+            FenError::InvalidFullMoves("-1".to_string()),
+        ),
+        (FEN_POSITIONS[0], FenError::InvalidKingCount("white", 0)),
+        ("4k3/8/8/8/8/8/8/2k1K3 w - - 0 1", FenError::InvalidKingCount("black", 2)),
+        ("4k2P/8/8/8/8/8/8/4K3 w - - 0 1", FenError::PawnOnBackRank(Sq::H8)),
+        ("4k3/8/8/8/8/8/8/p3K3 b - - 0 1", FenError::PawnOnBackRank(Sq::A1)),
+        // White to move could take the king
+        ("4k3/4R3/8/8/8/8/8/4K3 w - - 0 1", FenError::OpponentInCheck),
+    ];
+    for (fen_str, error) in cases {
+        assert_eq!(Board::from_fen(fen_str).err(), Some(error), "{}", fen_str);
+    }
+}
+
+#[test]
+fn set_fen_keeps_the_board_on_error() {
+    let mut board = Board::from_fen(FEN_POSITIONS[2]).unwrap();
+    let key = board.state.key;
+    for fen_str in ["4k3/4R3/8/8/8/8/8/4K3 w - - 0 1", "rnbqkbnr/pppppppp w KQkq - 0 1"] {
+        assert!(board.set_fen(fen_str).is_err());
+        assert_eq!(board.to_fen(), FEN_POSITIONS[2]);
+        assert_eq!(board.state.key, key);
+    }
+}
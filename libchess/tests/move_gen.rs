@@ -0,0 +1,138 @@
+use chess::board::Board;
+use chess::fen::{self, FEN_POSITIONS};
+use chess::move_gen::{self, MoveList};
+use chess::consts::Piece;
+use chess::moves::MoveUtil;
+
+fn perft(board: &mut Board, depth: usize) -> usize {
+    let mut ml = MoveList::new();
+    move_gen::generate_legal(board, &mut ml);
+    if depth == 1 {
+        return ml.moves.len();
+    }
+    let mut nodes = 0;
+    for mv in ml.moves {
+        let undo = board.make_move_unchecked(mv);
+        nodes += perft(board, depth - 1);
+        board.unmake_move(undo);
+    }
+    nodes
+}
+
+// Same as `perft` but with pseudo-legal generation and trial moves
+fn pseudo_legal_perft(board: &mut Board, depth: usize) -> usize {
+    if depth == 0 {
+        return 1;
+    }
+    let mut ml = MoveList::new();
+    move_gen::generate(board, &mut ml);
+    let mut nodes = 0;
+    for mv in ml.moves {
+        if let Some(undo) = board.make_move(mv) {
+            nodes += pseudo_legal_perft(board, depth - 1);
+            board.unmake_move(undo);
+        }
+    }
+    nodes
+}
+
+#[test]
+fn perft_standard_positions() {
+    let cases = [
+        (FEN_POSITIONS[1], 3, 8902),
+        (FEN_POSITIONS[2], 3, 97862),
+        (FEN_POSITIONS[3], 4, 43238),
+        (FEN_POSITIONS[4], 3, 9467),
+        (FEN_POSITIONS[5], 3, 62379),
+        (FEN_POSITIONS[6], 3, 89890),
+    ];
+    for (fen_str, depth, nodes) in cases {
+        let mut board = Board::from_fen(fen_str).unwrap();
+        assert_eq!(perft(&mut board, depth), nodes, "{}", fen_str);
+    }
+}
+
+#[test]
+fn pins_checks_and_en_passant() {
+    let cases = [
+        // En passant capture that would expose the king along the rank
+        ("8/8/8/8/k2Pp2Q/8/8/3K4 b - d3 0 1", 1, 6),
+        // Double check only allows king moves
+        ("4k3/8/8/8/8/5n2/8/r3K3 w - - 0 1", 1, 2),
+        // Small positions that exercise pins, evasions, en passant and castling legality
+        ("3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1", 4, 10138),
+        ("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1", 4, 13931),
+        ("r3k2r/1b4bq/8/8/8/8/7B/R3K2R w KQkq - 0 1", 3, 27826),
+        ("2K2r2/4P3/8/8/8/8/8/3k4 w - - 0 1", 4, 19174),
+        ("8/8/2k5/5q2/5n2/8/5K2/8 b - - 0 1", 3, 6559),
+    ];
+    for (fen_str, depth, nodes) in cases {
+        let mut board = Board::from_fen(fen_str).unwrap();
+        assert_eq!(perft(&mut board, depth), nodes, "{}", fen_str);
+        assert_eq!(pseudo_legal_perft(&mut board, depth), nodes, "{}", fen_str);
+    }
+}
+
+#[test]
+fn captures_and_quiets_split_all_moves() {
+    // Pawns that can promote both by pushing and by capturing
+    let promotions = [
+        "r3k2r/1P6/8/8/8/8/6p1/R3K2R w KQkq - 0 1",
+        "r3k2r/1P6/8/8/8/8/6p1/R3K2R b KQkq - 0 1",
+    ];
+    for fen_str in FEN_POSITIONS.into_iter().chain(promotions) {
+        let mut board = Board::new();
+        fen::parse(fen_str, &mut board).unwrap();
+        let mut all = MoveList::new();
+        let mut captures = MoveList::new();
+        let mut quiets = MoveList::new();
+        move_gen::generate(&board, &mut all);
+        move_gen::generate_captures(&board, &mut captures);
+        move_gen::generate_quiets(&board, &mut quiets);
+
+        for mv in &captures.moves {
+            let queen_promotion = matches!(mv.promoted(), Some(Piece::LQ | Piece::DQ));
+            assert!(mv.is_capture() || queen_promotion, "{}", mv.to_str());
+        }
+        // Under-promotions that capture are captures too
+        for mv in &quiets.moves {
+            assert!(!mv.is_capture(), "{}", mv.to_str());
+            assert!(!matches!(mv.promoted(), Some(Piece::LQ | Piece::DQ)), "{}", mv.to_str());
+        }
+        let mut split = [captures.moves, quiets.moves].concat();
+        split.sort_unstable();
+        all.moves.sort_unstable();
+        assert_eq!(split, all.moves, "{}", fen_str);
+    }
+}
+
+#[test]
+fn pseudo_legality_of_foreign_moves() {
+    let boards: Vec<Board> = FEN_POSITIONS
+        .iter()
+        .map(|fen_str| {
+            let mut board = Board::new();
+            fen::parse(fen_str, &mut board).unwrap();
+            board
+        })
+        .collect();
+    for board in &boards {
+        let mut own = MoveList::new();
+        move_gen::generate(board, &mut own);
+        assert!(!move_gen::is_pseudo_legal(board, 0));
+        // Moves generated for every other position, as a colliding hash entry could hold
+        for other in &boards {
+            let mut foreign = MoveList::new();
+            move_gen::generate(other, &mut foreign);
+            for mv in foreign.moves {
+                assert_eq!(
+                    move_gen::is_pseudo_legal(board, mv),
+                    own.moves.contains(&mv),
+                    "{} in {}",
+                    mv.to_str(),
+                    board.to_fen()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use chess::board::Board;
+use chess::fen::FEN_POSITIONS;
+use chess::pgn::{Game, PgnMove, PgnReader};
+
+const GAMES: &str = r#"[Event "Casual Game"]
+[Site "Berlin GER"]
+[Date "1852.??.??"]
+[Round "?"]
+[White "Adolf Anderssen"]
+[Black "Jean Dufresne"]
+[Result "1-0"]
+
+1.e4 e5 2.Nf3 Nc6 3.Bc4 Bc5 4.b4 Bxb4 5.c3 Ba5 6.d4 exd4 7.O-O
+d3 8.Qb3 Qf6 9.e5 Qg6 10.Re1 Nge7 11.Ba3 b5 12.Qxb5 Rb8 13.Qa4
+Bb6 14.Nbd2 Bb7 15.Ne4 Qf5 16.Bxd3 Qh5 17.Nf6+ gxf6 18.exf6
+Rg8 19.Rad1 Qxf3 20.Rxe7+ Nxe7 21.Qxd7+ Kxd7 22.Bf5+ Ke8
+23.Bd7+ Kf8 24.Bxe7# 1-0
+
+[Event "Annotated"]
+[Site "?"]
+[Date "????.??.??"]
+[Round "?"]
+[White "?"]
+[Black "?"]
+[Result "*"]
+[FEN "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"]
+
+{Kiwipete} 1. O-O-O! $14 {Long castle} 1... Bxe2 (1... hxg2 2. Rhg1 ; rest of line
+(2. Qxg2?)) 2. Nxe2 0-0 3. e8Q?? *
+"#;
+
+#[test]
+fn reads_multiple_games() {
+    let games: Vec<_> = PgnReader::new(GAMES.as_bytes()).collect();
+    assert_eq!(games.len(), 2);
+
+    let immortal = games[0].as_ref().unwrap();
+    assert_eq!(immortal.tag("White"), Some("Adolf Anderssen"));
+    assert_eq!(immortal.result, "1-0");
+    assert_eq!(immortal.mainline().count(), 47);
+
+    // 'e8Q' isn't legal in the second game
+    assert!(games[1].is_err());
+}
+
+#[test]
+fn reads_games_without_tags() {
+    // The result is all that separates these games, one of them on the same line
+    let pgn = "1. e4 e5 2. Nf3 {a result in a comment: 1-0} Nc6 (2... d6) 1/2-1/2\n\
+               1. d4 d5 0-1 1. c4 *\n\
+               \n\
+               1. Nf3 1-0\n";
+    let games: Vec<Game> = PgnReader::new(pgn.as_bytes())
+        .map(Result::unwrap)
+        .collect();
+    let summary: Vec<_> = games
+        .iter()
+        .map(|game| (game.mainline().count(), game.result.as_str()))
+        .collect();
+    assert_eq!(summary, [(4, "1/2-1/2"), (2, "0-1"), (1, "*"), (1, "1-0")]);
+}
+
+#[test]
+fn round_trip() {
+    let annotated = GAMES.replace(" 3. e8Q??", "");
+    for game in PgnReader::new(annotated.as_bytes()) {
+        let game = game.unwrap();
+        let pgn = game.to_pgn();
+        assert!(pgn.lines().all(|line| line.len() <= 80));
+
+        let reread: Vec<Game> = PgnReader::new(pgn.as_bytes())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(reread.len(), 1);
+        assert_eq!(reread[0].to_pgn(), pgn);
+    }
+}
+
+#[test]
+fn custom_start_position_is_exported() {
+    let mut game = Game::new();
+    game.start = Board::from_fen(FEN_POSITIONS[3]).unwrap();
+    let mut board = game.start;
+    for san in ["e4", "Kg5"] {
+        let mv = board.parse_san(san).unwrap();
+        board.make_move_unchecked(mv);
+        game.moves.push(PgnMove::new(mv));
+    }
+    // Nothing in the tags says where the game starts
+    assert!(game.tags.is_empty());
+    let pgn = game.to_pgn();
+    assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1\"]\n"));
+
+    let reread = PgnReader::new(pgn.as_bytes()).next().unwrap().unwrap();
+    assert_eq!(reread.start.to_fen(), FEN_POSITIONS[3]);
+    assert!(reread.mainline().eq(game.mainline()));
+    assert_eq!(reread.to_pgn(), pgn);
+
+    // The standard start position needs neither tag
+    assert!(!Game::new().to_pgn().contains("FEN"));
+}
+
+#[test]
+fn annotations() {
+    let annotated = GAMES.replace(" 3. e8Q??", "");
+    let game = PgnReader::new(annotated.as_bytes())
+        .nth(1)
+        .unwrap()
+        .unwrap();
+    let pgn = game.to_pgn().replace('\n', " ");
+    assert!(pgn.contains(
+        "{Kiwipete} 1. O-O-O $1 $14 {Long castle} 1... Bxe2 (1... hxg2 2. Rhg1 {rest of line} (2. Qxg2 $2)) 2. Nxe2 O-O *"
+    ));
+    assert_eq!(game.moves[0].nags, vec![1, 14]);
+    assert_eq!(game.moves[1].variations.len(), 1);
+    assert_eq!(game.moves[1].variations[0][1].variations.len(), 1);
+}
@@ -0,0 +1,70 @@
+use chess::board::Board;
+use chess::fen::{self, FEN_POSITIONS};
+use chess::move_gen::{self, MoveList};
+use chess::moves::MoveUtil;
+
+#[test]
+fn round_trip_legal_moves() {
+    for fen_str in FEN_POSITIONS {
+        let mut board = Board::new();
+        fen::parse(fen_str, &mut board).unwrap();
+        let mut ml = MoveList::new();
+        move_gen::generate(&board, &mut ml);
+        for mv in ml.moves {
+            let Some(undo) = board.make_move(mv) else {
+                continue;
+            };
+            board.unmake_move(undo);
+            let san = board.move_to_san(mv);
+            assert_eq!(board.parse_san(&san), Ok(mv), "{}", san);
+        }
+    }
+}
+
+#[test]
+fn formatting() {
+    let cases = [
+        (FEN_POSITIONS[1], "g1f3", "Nf3"),
+        (FEN_POSITIONS[2], "e1g1", "O-O"),
+        (FEN_POSITIONS[2], "e1c1", "O-O-O"),
+        (FEN_POSITIONS[2], "e5f7", "Nxf7"),
+        (FEN_POSITIONS[2], "d5e6", "dxe6"),
+        (FEN_POSITIONS[2], "e2b5", "Bb5"),
+        (FEN_POSITIONS[7], "f5e6", "fxe6"),
+        (FEN_POSITIONS[7], "g7h8Q", "gxh8=Q"),
+        ("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1", "a1a8", "Ra8+"),
+        ("6k1/5ppp/8/8/8/8/8/R3K3 w Q - 0 1", "a1a8", "Ra8#"),
+        ("2k5/8/8/8/R6R/8/8/R3K3 w - - 0 1", "a1a2", "R1a2"),
+        ("2k5/8/8/8/R6R/8/8/R3K3 w - - 0 1", "a4d4", "Rad4"),
+        ("8/7k/8/8/8/Q7/8/Q1Q1K3 w - - 0 1", "a1b2", "Qa1b2"),
+    ];
+    for (fen_str, coords, san) in cases {
+        let board = Board::from_fen(fen_str).unwrap();
+        let mut ml = MoveList::new();
+        move_gen::generate(&board, &mut ml);
+        let mv = *ml.moves.iter().find(|mv| mv.to_str().trim() == coords).unwrap();
+        assert_eq!(board.move_to_san(mv), san);
+    }
+}
+
+#[test]
+fn parsing_variants() {
+    let board = Board::from_fen(FEN_POSITIONS[2]).unwrap();
+    let castle = board.parse_san("O-O").unwrap();
+    assert_eq!(board.parse_san("0-0"), Ok(castle));
+    let capture = board.parse_san("Nxf7").unwrap();
+    assert_eq!(board.parse_san("Nxf7+!?"), Ok(capture));
+    assert_eq!(board.parse_san("Nf7"), Ok(capture));
+
+    let board = Board::from_fen(FEN_POSITIONS[7]).unwrap();
+    let promotion = board.parse_san("gxh8=Q").unwrap();
+    assert_eq!(board.parse_san("gxh8Q"), Ok(promotion));
+    assert_eq!(board.parse_san("gxh8q"), Ok(promotion));
+    assert!(board.parse_san("gxh8").is_err());
+
+    let board = Board::from_fen("2k5/8/8/8/R6R/8/8/R3K3 w - - 0 1").unwrap();
+    assert!(board.parse_san("Rd4").is_err());
+    assert!(board.parse_san("Ke2").is_ok());
+    assert!(board.parse_san("Ke3").is_err());
+    assert!(board.parse_san("Zz9").is_err());
+}
@@ -0,0 +1,58 @@
+use chess::board::Board;
+use chess::fen::{self, FEN_POSITIONS};
+use chess::move_gen::{self, MoveList};
+use chess::moves::MoveUtil;
+
+#[test]
+fn exchanges() {
+    let cases = [
+        // Undefended pawn
+        ("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5", 100),
+        // Both sides line up more attackers behind the first
+        ("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1", "d3e5", -200),
+        // The queen backs the rook up through the square it leaves
+        ("3r2k1/8/8/3p4/8/8/3R4/3Q2K1 w - - 0 1", "d2d5", 100),
+        ("3r2k1/8/8/3p4/8/8/8/3RK3 w - - 0 1", "d1d5", -400),
+        // The king can only take back on a square that isn't covered
+        ("8/8/4k3/3p4/8/2N5/8/3RK3 w - - 0 1", "c3d5", 100),
+        ("8/8/4k3/3p4/8/2N5/8/4K3 w - - 0 1", "c3d5", -200),
+        ("4k3/8/8/8/3r4/8/3r4/4K3 w - - 0 1", "e1d2", -19500),
+        ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", 100),
+        ("3r3k/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7d8Q", 1300),
+        ("3r3k/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7e8Q", -100),
+        ("3r3k/8/8/8/8/8/8/4K2R w K - 0 1", "e1g1", 0),
+    ];
+    for (fen_str, coords, value) in cases {
+        let mut board = Board::new();
+        fen::parse(fen_str, &mut board).unwrap();
+        let mut ml = MoveList::new();
+        move_gen::generate(&board, &mut ml);
+        let mv = *ml.moves.iter().find(|mv| mv.to_str().trim() == coords).unwrap();
+        assert_eq!(board.see(mv), value, "{} in {}", coords, fen_str);
+        assert!(board.see_ge(mv, value), "{} in {}", coords, fen_str);
+        assert!(!board.see_ge(mv, value + 1), "{} in {}", coords, fen_str);
+    }
+}
+
+#[test]
+fn threshold_agrees_with_value() {
+    for fen_str in FEN_POSITIONS {
+        let mut board = Board::new();
+        fen::parse(fen_str, &mut board).unwrap();
+        let mut ml = MoveList::new();
+        move_gen::generate(&board, &mut ml);
+        for mv in ml.moves {
+            let value = board.see(mv);
+            for threshold in (-1000..=1000).step_by(50).chain([value, value + 1]) {
+                assert_eq!(
+                    board.see_ge(mv, threshold),
+                    value >= threshold,
+                    "{} at {} in {}",
+                    mv.to_str().trim(),
+                    threshold,
+                    fen_str
+                );
+            }
+        }
+    }
+}
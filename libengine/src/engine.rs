@@ -1,7 +1,9 @@
-use chess::attack::AttackInfo;
-use chess::board::Board;
+use chess::attack;
+use chess::board::{Board, Undo};
 use crate::eval::EvalMasks;
+use crate::options::Options;
 use crate::search::SearchInfo;
+use crate::timeman;
 use crate::uci::UCIState;
 
 pub const VERSION: &str = "0.3";
@@ -11,32 +13,43 @@ use std::sync::{Arc, RwLock};
 use std::thread::JoinHandle;
 
 pub struct Engine {
-    pub attack_info: AttackInfo,
     pub board: Board,
+    // Undo records of the moves played since the position was set, for repetition detection
+    pub undo_stack: Vec<Undo>,
     pub eval_mask: EvalMasks,
     pub search_info: SearchInfo,
-    // pub zobrist_info: ZobristInfo,
     pub uci_state: Arc<RwLock<UCIState>>,
     pub search_thread: Option<JoinHandle<()>>,
     pub worker_thread_count: usize,
+    // Milliseconds kept in reserve on every move for communication lag
+    pub move_overhead: u32,
+    pub options: Options,
 
     pub debug: bool,
 }
 
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Engine {
     pub fn new() -> Self {
         let mut this = Self {
-            attack_info: AttackInfo::new(),
             board: Board::new(),
+            undo_stack: Vec::new(),
             eval_mask: EvalMasks::new(),
             search_info: SearchInfo::new(),
             uci_state: Arc::new(RwLock::new(UCIState::new())),
             search_thread: None,
             worker_thread_count: NUM_OF_THREADS,
+            move_overhead: timeman::DEFAULT_MOVE_OVERHEAD,
+            options: Options::new(),
             debug: false,
         };
         // Initialize attributes
-        this.attack_info.init();
+        attack::init();
         this.eval_mask.init();
 
         this
@@ -1,4 +1,4 @@
-use chess::attack::AttackInfo;
+use chess::attack;
 use chess::bb::{BBUtil, BB};
 use chess::board::Position;
 use chess::consts::{Piece, PieceColor, Sq};
@@ -77,6 +77,7 @@ fn set_file_and_rank(rank: i32, file: i32) -> BB {
     mask
 }
 
+#[allow(clippy::needless_range_loop)]
 fn get_phase_score(pos: &Position) -> i32 {
     let mut white_piece_score = 0;
     let mut black_piece_score = 0;
@@ -91,10 +92,10 @@ fn get_phase_score(pos: &Position) -> i32 {
     white_piece_score + black_piece_score
 }
 
+#[allow(clippy::needless_range_loop)]
 pub fn evaluate(
     pos: &Position,
     side: PieceColor,
-    attack_info: &AttackInfo,
     mask: &EvalMasks,
 ) -> i32 {
     let phase_score = get_phase_score(pos);
@@ -131,7 +132,6 @@ pub fn evaluate(
                         eval_light_pieces(
                             p,
                             pos,
-                            attack_info,
                             mask,
                             sq,
                             &mut opening,
@@ -147,7 +147,6 @@ pub fn evaluate(
                         eval_dark_pieces(
                             p,
                             pos,
-                            attack_info,
                             mask,
                             sq,
                             &mut opening,
@@ -178,7 +177,6 @@ pub fn evaluate(
 fn eval_light_pieces(
     white_piece: Piece,
     pos: &Position,
-    attack_info: &AttackInfo,
     mask: &EvalMasks,
     sq: usize,
     opening: &mut i32,
@@ -204,13 +202,11 @@ fn eval_light_pieces(
 
         Piece::LB => {
             let both_units = pos.units(PieceColor::Both);
-            *opening += (attack_info
-                .get_bishop_attack(Sq::from_num(sq), both_units)
+            *opening += (attack::bishop_attacks(Sq::from_num(sq), both_units)
                 .count_ones() as i32
                 - BISHOP_UNIT)
                 * BISHOP_MOBILITY_BONUS[Phase::Opening as usize];
-            *endgame += (attack_info
-                .get_bishop_attack(Sq::from_num(sq), both_units)
+            *endgame += (attack::bishop_attacks(Sq::from_num(sq), both_units)
                 .count_ones() as i32
                 - BISHOP_UNIT)
                 * BISHOP_MOBILITY_BONUS[Phase::Endgame as usize];
@@ -231,13 +227,11 @@ fn eval_light_pieces(
 
         Piece::LQ => {
             let both_units = pos.units(PieceColor::Both);
-            *opening += (attack_info
-                .get_queen_attack(Sq::from_num(sq), both_units)
+            *opening += (attack::queen_attacks(Sq::from_num(sq), both_units)
                 .count_ones() as i32
                 - QUEEN_UNIT)
                 * QUEEN_MOBILITY_BONUS[Phase::Opening as usize];
-            *endgame += (attack_info
-                .get_queen_attack(Sq::from_num(sq), both_units)
+            *endgame += (attack::queen_attacks(Sq::from_num(sq), both_units)
                 .count_ones() as i32
                 - QUEEN_UNIT)
                 * QUEEN_MOBILITY_BONUS[Phase::Endgame as usize];
@@ -258,10 +252,10 @@ fn eval_light_pieces(
             }
             // King safety bonus
             let light_units = pos.units(PieceColor::Light);
-            *opening += (attack_info.king[sq] & light_units).count_ones()
+            *opening += (attack::king_attacks(sq) & light_units).count_ones()
                 as i32
                 * KING_SHIELD_BONUS;
-            *endgame += (attack_info.king[sq] & light_units).count_ones()
+            *endgame += (attack::king_attacks(sq) & light_units).count_ones()
                 as i32
                 * KING_SHIELD_BONUS;
         }
@@ -272,7 +266,6 @@ fn eval_light_pieces(
 fn eval_dark_pieces(
     black_piece: Piece,
     pos: &Position,
-    attack_info: &AttackInfo,
     mask: &EvalMasks,
     sq: usize,
     opening: &mut i32,
@@ -298,13 +291,11 @@ fn eval_dark_pieces(
 
         Piece::DB => {
             let both_units = pos.units(PieceColor::Both);
-            *opening -= (attack_info
-                .get_bishop_attack(Sq::from_num(sq), both_units)
+            *opening -= (attack::bishop_attacks(Sq::from_num(sq), both_units)
                 .count_ones() as i32
                 - BISHOP_UNIT)
                 * BISHOP_MOBILITY_BONUS[Phase::Opening as usize];
-            *endgame -= (attack_info
-                .get_bishop_attack(Sq::from_num(sq), both_units)
+            *endgame -= (attack::bishop_attacks(Sq::from_num(sq), both_units)
                 .count_ones() as i32
                 - BISHOP_UNIT)
                 * BISHOP_MOBILITY_BONUS[Phase::Endgame as usize];
@@ -325,13 +316,11 @@ fn eval_dark_pieces(
 
         Piece::DQ => {
             let both_units = pos.units(PieceColor::Both);
-            *opening -= (attack_info
-                .get_queen_attack(Sq::from_num(sq), both_units)
+            *opening -= (attack::queen_attacks(Sq::from_num(sq), both_units)
                 .count_ones() as i32
                 - QUEEN_UNIT)
                 * QUEEN_MOBILITY_BONUS[Phase::Opening as usize];
-            *endgame -= (attack_info
-                .get_queen_attack(Sq::from_num(sq), both_units)
+            *endgame -= (attack::queen_attacks(Sq::from_num(sq), both_units)
                 .count_ones() as i32
                 - QUEEN_UNIT)
                 * QUEEN_MOBILITY_BONUS[Phase::Endgame as usize];
@@ -352,10 +341,10 @@ fn eval_dark_pieces(
             }
             // King safety bonus
             let dark_units = pos.units(PieceColor::Dark);
-            *opening -= (attack_info.king[sq] & dark_units).count_ones()
+            *opening -= (attack::king_attacks(sq) & dark_units).count_ones()
                 as i32
                 * KING_SHIELD_BONUS;
-            *endgame -= (attack_info.king[sq] & dark_units).count_ones()
+            *endgame -= (attack::king_attacks(sq) & dark_units).count_ones()
                 as i32
                 * KING_SHIELD_BONUS;
         }
@@ -1,7 +1,10 @@
 pub mod engine;
 mod eval;
+mod movepick;
+pub mod options;
 mod perft;
 mod search;
 mod threads;
+mod timeman;
 mod tt;
 pub mod uci;
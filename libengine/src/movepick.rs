@@ -0,0 +1,200 @@
+use chess::board::Board;
+use chess::consts::Piece;
+use chess::move_gen::{self, MoveList};
+use chess::moves::{Move, MoveUtil};
+use crate::search::SearchInfo;
+
+// [attacker][victim]
+const MVV_LVA: [[i32; 6]; 6] = [
+    [105, 205, 305, 405, 505, 605],
+    [104, 204, 304, 404, 504, 604],
+    [103, 203, 303, 403, 503, 603],
+    [102, 202, 302, 402, 502, 602],
+    [101, 201, 301, 401, 501, 601],
+    [100, 200, 300, 400, 500, 600],
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Stage {
+    HashMove,
+    GenCaptures,
+    GoodCaptures,
+    Killer1,
+    Killer2,
+    Countermove,
+    GenQuiets,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+/// Hands out the pseudo-legal moves of a position one at a time, best guesses first:
+/// the hash move, good captures, killers, the countermove, quiets by history and finally
+/// bad captures. Each group is only generated once the ones before it are used up, so a
+/// node that is cut off by an early move never generates the rest.
+pub struct MovePicker {
+    stage: Stage,
+    hash_move: Move,
+    killers: [Move; 2],
+    countermove: Move,
+    // Moves of the current stage with their ordering scores, handed out up to `index`
+    moves: Vec<(Move, i32)>,
+    index: usize,
+    bad_captures: Vec<Move>,
+    // Quiescence search only looks at captures
+    captures_only: bool,
+}
+
+// Moves that `generate_captures` produces: captures and queen promotions
+fn is_tactical(mv: Move) -> bool {
+    mv.is_capture() || matches!(mv.promoted(), Some(Piece::LQ | Piece::DQ))
+}
+
+fn captured_piece(board: &Board, mv: Move) -> usize {
+    // Nothing stands on the target of an en passant capture or a plain promotion
+    board
+        .find_piece(mv.target() as usize)
+        .map_or(Piece::LP as usize, |piece| piece as usize % 6)
+}
+
+impl MovePicker {
+    pub fn new(info: &SearchInfo, hash_move: Move) -> Self {
+        let ply = info.ply as usize;
+        // The reply that refuted the opponent's last move elsewhere in the tree
+        let countermove = match info.undo_stack.last() {
+            Some(undo) if undo.mv != 0 => {
+                info.countermove[undo.mv.piece() as usize][undo.mv.target() as usize]
+            }
+            _ => 0,
+        };
+        Self {
+            stage: Stage::HashMove,
+            hash_move,
+            killers: [info.killer[0][ply], info.killer[1][ply]],
+            countermove,
+            moves: vec![],
+            index: 0,
+            bad_captures: vec![],
+            captures_only: false,
+        }
+    }
+
+    pub fn new_quiescence() -> Self {
+        Self {
+            stage: Stage::GenCaptures,
+            hash_move: 0,
+            killers: [0; 2],
+            countermove: 0,
+            moves: vec![],
+            index: 0,
+            bad_captures: vec![],
+            captures_only: true,
+        }
+    }
+
+    pub fn next(&mut self, info: &SearchInfo, board: &Board) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::GenCaptures;
+                    if move_gen::is_pseudo_legal(board, self.hash_move) {
+                        return Some(self.hash_move);
+                    }
+                }
+                Stage::GenCaptures => {
+                    let mut ml = MoveList::new();
+                    move_gen::generate_captures(board, &mut ml);
+                    self.load(ml, |mv| MVV_LVA[mv.piece() as usize % 6][captured_piece(board, mv)]);
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => match self.pick_best() {
+                    // Captures that lose material once the exchange plays out wait until the end
+                    Some(mv) if board.see_ge(mv, 0) => return Some(mv),
+                    Some(mv) => self.bad_captures.push(mv),
+                    None => {
+                        self.stage = if self.captures_only {
+                            Stage::BadCaptures
+                        } else {
+                            Stage::Killer1
+                        };
+                        self.index = 0;
+                    }
+                },
+                Stage::Killer1 => {
+                    self.stage = Stage::Killer2;
+                    if self.is_refutation(board, self.killers[0]) {
+                        return Some(self.killers[0]);
+                    }
+                }
+                Stage::Killer2 => {
+                    self.stage = Stage::Countermove;
+                    if self.killers[1] != self.killers[0]
+                        && self.is_refutation(board, self.killers[1])
+                    {
+                        return Some(self.killers[1]);
+                    }
+                }
+                Stage::Countermove => {
+                    self.stage = Stage::GenQuiets;
+                    if !self.killers.contains(&self.countermove)
+                        && self.is_refutation(board, self.countermove)
+                    {
+                        return Some(self.countermove);
+                    }
+                }
+                Stage::GenQuiets => {
+                    let mut ml = MoveList::new();
+                    move_gen::generate_quiets(board, &mut ml);
+                    self.load(ml, |mv| {
+                        info.history[mv.piece() as usize][mv.target() as usize] as i32
+                    });
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => match self.pick_best() {
+                    Some(mv) => return Some(mv),
+                    None => {
+                        self.stage = Stage::BadCaptures;
+                        self.index = 0;
+                    }
+                },
+                Stage::BadCaptures => {
+                    // Already in MVV-LVA order
+                    if let Some(mv) = self.bad_captures.get(self.index) {
+                        self.index += 1;
+                        return Some(*mv);
+                    }
+                    self.stage = Stage::Done;
+                }
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    // Killers and countermoves are quiet moves from other positions, so they have to be
+    // checked against this one
+    fn is_refutation(&self, board: &Board, mv: Move) -> bool {
+        mv != self.hash_move && !is_tactical(mv) && move_gen::is_pseudo_legal(board, mv)
+    }
+
+    // Replaces the moves of the last stage with a newly generated batch, leaving out
+    // the ones an earlier stage already handed out
+    fn load(&mut self, ml: MoveList, score: impl Fn(Move) -> i32) {
+        let skip = [self.hash_move, self.killers[0], self.killers[1], self.countermove];
+        self.moves = ml
+            .moves
+            .into_iter()
+            .filter(|mv| !skip.contains(mv) || (is_tactical(*mv) && *mv != self.hash_move))
+            .map(|mv| (mv, score(mv)))
+            .collect();
+        self.index = 0;
+    }
+
+    // One step of a selection sort, so the moves that are never asked for aren't sorted
+    fn pick_best(&mut self) -> Option<Move> {
+        let rest = self.moves.get_mut(self.index..)?;
+        let best = (0..rest.len()).max_by_key(|&i| (rest[i].1, std::cmp::Reverse(i)))?;
+        rest.swap(0, best);
+        self.index += 1;
+        Some(rest[0].0)
+    }
+}
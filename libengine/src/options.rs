@@ -0,0 +1,270 @@
+use crate::engine::Engine;
+use crate::timeman;
+use crate::tt;
+
+use std::fmt;
+use std::sync::Arc;
+
+pub const MAX_THREADS: i64 = 64;
+// More lines than there can be legal moves would never be used
+pub const MAX_MULTI_PV: i64 = 256;
+
+/// The UCI option types along with their limits and defaults
+pub enum OptionKind {
+    Spin { default: i64, min: i64, max: i64 },
+    Check { default: bool },
+    Combo { default: &'static str, vars: &'static [&'static str] },
+    Button,
+    String { default: &'static str },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptionValue {
+    Spin(i64),
+    Check(bool),
+    Combo(String),
+    Button,
+    String(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OptionError {
+    UnknownOption(String),
+    MissingValue(&'static str),
+    InvalidValue(&'static str, String),
+}
+
+impl fmt::Display for OptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownOption(name) => write!(f, "unknown option '{}'", name),
+            Self::MissingValue(name) => write!(f, "option '{}' needs a value", name),
+            Self::InvalidValue(name, value) => {
+                write!(f, "'{}' isn't a valid value for option '{}'", value, name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OptionError {}
+
+pub struct EngineOption {
+    pub name: &'static str,
+    pub kind: OptionKind,
+    pub value: OptionValue,
+    // Applies a new value to the engine; called after the value has been validated and stored
+    on_change: fn(&mut Engine, &OptionValue),
+}
+
+impl EngineOption {
+    fn new(name: &'static str, kind: OptionKind, on_change: fn(&mut Engine, &OptionValue)) -> Self {
+        let value = match &kind {
+            OptionKind::Spin { default, .. } => OptionValue::Spin(*default),
+            OptionKind::Check { default } => OptionValue::Check(*default),
+            OptionKind::Combo { default, .. } => OptionValue::Combo(default.to_string()),
+            OptionKind::Button => OptionValue::Button,
+            OptionKind::String { default } => OptionValue::String(default.to_string()),
+        };
+        Self {
+            name,
+            kind,
+            value,
+            on_change,
+        }
+    }
+
+    fn parse_value(&self, value: Option<&str>) -> Result<OptionValue, OptionError> {
+        if let OptionKind::Button = self.kind {
+            return Ok(OptionValue::Button);
+        }
+        let value = value.ok_or(OptionError::MissingValue(self.name))?;
+        let invalid = || OptionError::InvalidValue(self.name, value.to_string());
+        match &self.kind {
+            OptionKind::Spin { min, max, .. } => match value.parse::<i64>() {
+                Ok(num) if (*min..=*max).contains(&num) => Ok(OptionValue::Spin(num)),
+                _ => Err(invalid()),
+            },
+            OptionKind::Check { .. } => match value {
+                "true" => Ok(OptionValue::Check(true)),
+                "false" => Ok(OptionValue::Check(false)),
+                _ => Err(invalid()),
+            },
+            OptionKind::Combo { vars, .. } => vars
+                .iter()
+                .find(|var| var.eq_ignore_ascii_case(value))
+                .map(|var| OptionValue::Combo(var.to_string()))
+                .ok_or_else(invalid),
+            OptionKind::Button => unreachable!(),
+            // '<empty>' is how GUIs send an empty string
+            OptionKind::String { .. } => Ok(OptionValue::String(if value == "<empty>" {
+                String::new()
+            } else {
+                value.to_string()
+            })),
+        }
+    }
+}
+
+impl fmt::Display for EngineOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "option name {} type ", self.name)?;
+        match &self.kind {
+            OptionKind::Spin { default, min, max } => {
+                write!(f, "spin default {} min {} max {}", default, min, max)
+            }
+            OptionKind::Check { default } => write!(f, "check default {}", default),
+            OptionKind::Combo { default, vars } => {
+                write!(f, "combo default {}", default)?;
+                for var in vars.iter() {
+                    write!(f, " var {}", var)?;
+                }
+                Ok(())
+            }
+            OptionKind::Button => write!(f, "button"),
+            OptionKind::String { default } => {
+                let default = if default.is_empty() { "<empty>" } else { default };
+                write!(f, "string default {}", default)
+            }
+        }
+    }
+}
+
+/// Every option the engine supports. New options only need to be added to `Options::new`.
+pub struct Options {
+    list: Vec<EngineOption>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Options {
+    pub fn new() -> Self {
+        let list = vec![
+            EngineOption::new(
+                "Hash",
+                OptionKind::Spin {
+                    default: tt::DEFAULT_HASH_MB as i64,
+                    min: tt::MIN_HASH_MB as i64,
+                    max: tt::MAX_HASH_MB as i64,
+                },
+                |engine, value| {
+                    // A search still running keeps its own handle on the old table
+                    if let OptionValue::Spin(mb) = value {
+                        engine.search_info.tt = Arc::new(tt::HashTT::new(*mb as usize));
+                    }
+                },
+            ),
+            EngineOption::new("Clear Hash", OptionKind::Button, |engine, _| {
+                engine.search_info.tt.clear_table();
+            }),
+            // Tells the engine the GUI may send 'go ponder'; there is nothing to set up for it
+            EngineOption::new("Ponder", OptionKind::Check { default: false }, |_, _| {}),
+            EngineOption::new(
+                "MultiPV",
+                OptionKind::Spin {
+                    default: 1,
+                    min: 1,
+                    max: MAX_MULTI_PV,
+                },
+                |engine, value| {
+                    if let OptionValue::Spin(lines) = value {
+                        engine.search_info.multi_pv = *lines as usize;
+                    }
+                },
+            ),
+            EngineOption::new(
+                "Move Overhead",
+                OptionKind::Spin {
+                    default: timeman::DEFAULT_MOVE_OVERHEAD as i64,
+                    min: 0,
+                    max: timeman::MAX_MOVE_OVERHEAD as i64,
+                },
+                |engine, value| {
+                    if let OptionValue::Spin(ms) = value {
+                        engine.move_overhead = *ms as u32;
+                    }
+                },
+            ),
+            EngineOption::new(
+                "Reverse Futility Pruning",
+                OptionKind::Check { default: true },
+                |engine, value| {
+                    if let OptionValue::Check(on) = value {
+                        engine.search_info.pruning.reverse_futility = *on;
+                    }
+                },
+            ),
+            EngineOption::new("Razoring", OptionKind::Check { default: true }, |engine, value| {
+                if let OptionValue::Check(on) = value {
+                    engine.search_info.pruning.razoring = *on;
+                }
+            }),
+            EngineOption::new(
+                "Futility Pruning",
+                OptionKind::Check { default: true },
+                |engine, value| {
+                    if let OptionValue::Check(on) = value {
+                        engine.search_info.pruning.futility = *on;
+                    }
+                },
+            ),
+            EngineOption::new(
+                "Late Move Pruning",
+                OptionKind::Check { default: true },
+                |engine, value| {
+                    if let OptionValue::Check(on) = value {
+                        engine.search_info.pruning.late_move = *on;
+                    }
+                },
+            ),
+            EngineOption::new("SEE Pruning", OptionKind::Check { default: true }, |engine, value| {
+                if let OptionValue::Check(on) = value {
+                    engine.search_info.pruning.see = *on;
+                }
+            }),
+            EngineOption::new(
+                "Threads",
+                OptionKind::Spin {
+                    default: 1,
+                    min: 1,
+                    max: MAX_THREADS,
+                },
+                |engine, value| {
+                    if let OptionValue::Spin(threads) = value {
+                        engine.worker_thread_count = *threads as usize;
+                    }
+                },
+            ),
+        ];
+        Self { list }
+    }
+
+    /// Option names aren't case sensitive
+    pub fn get(&self, name: &str) -> Option<&EngineOption> {
+        self.list.iter().find(|opt| opt.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn print(&self) {
+        for opt in &self.list {
+            println!("{}", opt);
+        }
+    }
+}
+
+/// Validates and stores the new value of an option, then applies it to the engine
+pub fn set_option(engine: &mut Engine, name: &str, value: Option<&str>) -> Result<(), OptionError> {
+    let opt = engine
+        .options
+        .list
+        .iter_mut()
+        .find(|opt| opt.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| OptionError::UnknownOption(name.to_string()))?;
+    let new_value = opt.parse_value(value)?;
+    opt.value = new_value.clone();
+    let on_change = opt.on_change;
+    on_change(engine, &new_value);
+    Ok(())
+}
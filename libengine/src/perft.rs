@@ -1,11 +1,9 @@
-use chess::attack::AttackInfo;
 use chess::board::Board;
 use chess::move_gen::{self, MoveList};
-use chess::moves::{self, MoveFlag, MoveUtil};
+use chess::moves::MoveUtil;
 
 fn driver(
     board: &mut Board,
-    attack_info: &AttackInfo,
     depth: usize,
     node_count: &mut usize,
 ) {
@@ -15,38 +13,34 @@ fn driver(
     }
 
     let mut ml = MoveList::new();
-    move_gen::generate(board, attack_info, &mut ml);
-    let mut clone;
+    move_gen::generate_legal(board, &mut ml);
+    // Every generated move is legal, so the last ply only needs counting
+    if depth == 1 {
+        *node_count += ml.moves.len();
+        return;
+    }
     for mv in &ml.moves {
-        clone = board.clone();
-        if !moves::play(board, attack_info, *mv, MoveFlag::AllMoves) {
-            continue;
-        }
-        driver(board, attack_info, depth - 1, node_count);
-        *board = clone;
+        let undo = board.make_move_unchecked(*mv);
+        driver(board, depth - 1, node_count);
+        board.unmake_move(undo);
     }
 }
 
-pub fn test(board: &mut Board, attack_info: &AttackInfo, depth: usize) {
+pub fn test(board: &mut Board, depth: usize) {
     let mut total_nodes = 0;
     let mut ml = MoveList::new();
-    move_gen::generate(board, attack_info, &mut ml);
+    move_gen::generate_legal(board, &mut ml);
 
-    let mut clone;
     for mv in &ml.moves {
-        clone = board.clone();
-        if !moves::play(board, attack_info, *mv, MoveFlag::AllMoves) {
-            continue;
-        }
+        let undo = board.make_move_unchecked(*mv);
         // Nodes searched so far
         let nodes_searched = total_nodes;
         driver(
             board,
-            attack_info,
             depth - 1,
             &mut total_nodes,
         );
-        *board = clone;
+        board.unmake_move(undo);
         let move_str = mv.to_str();
         let move_str = if mv.promoted().is_some() {
             move_str
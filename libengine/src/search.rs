@@ -1,21 +1,38 @@
-use chess::attack::AttackInfo;
-use chess::bb::BBUtil;
-use chess::board::{self, Board};
-use chess::consts::{Piece, PieceColor, Sq};
+use chess::board::{self, Board, Undo};
 use crate::eval::{self, EvalMasks};
 use crate::engine::Engine;
 use chess::move_gen::{self, MoveList};
-use chess::moves::{self, Move, MoveFlag, MoveUtil};
-use crate::threads;
+use chess::moves::{Move, MoveUtil};
+use crate::movepick::MovePicker;
+use crate::threads::{self, PvLine, SearchResult, SharedSearch};
+use crate::timeman::SearchProgress;
 use crate::tt::{HashTT, TTFlag};
-use crate::uci::{self, UCIState};
-use chess::zobrist::{ZobristInfo, self, ZobristAction};
+use crate::uci::{self, SearchLimits, UCIState};
 
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::Duration;
 
-const FULL_DEPTH_MOVES: u32 = 4;
+const FULL_DEPTH_MOVES: usize = 4;
 const REDUCTION_LIMIT: u32 = 3;
 pub const MAX_SEARCH_PLY: usize = 64;
+// Move numbers past this share the last column of the reduction table
+const LMR_MOVES: usize = 64;
+// History score worth one ply less of reduction. History only grows by the depth of
+// each alpha raise, so a move past this has improved alpha many times in this search
+const LMR_HISTORY_DIVISOR: u32 = 64;
+
+// Late move reductions by [depth][move number]: logarithmic in both, so they grow
+// quickly at first and then level off
+static LMR_TABLE: LazyLock<[[i32; LMR_MOVES]; MAX_SEARCH_PLY]> = LazyLock::new(|| {
+    let mut table = [[0; LMR_MOVES]; MAX_SEARCH_PLY];
+    for (depth, row) in table.iter_mut().enumerate().skip(1) {
+        for (move_number, reduction) in row.iter_mut().enumerate().skip(1) {
+            *reduction = (0.75 + (depth as f64).ln() * (move_number as f64).ln() / 2.25) as i32;
+        }
+    }
+    table
+});
 
 // Mating score bounds
 // [-INFINITY, -MATE_VALUE ... -MATE_SCORE, ... SCORE ... MATE_SCORE ... MATE_VALUE, INFINITY]
@@ -23,29 +40,72 @@ const INFINITY: i32 = 50000;
 const MATE_VALUE: i32 = 49000; // Upper bound
 pub const MATE_SCORE: i32 = 48000; // Lower bound
 
-const MVV_LVA: [[u32; 6]; 6] = [
-    [105, 205, 305, 405, 505, 605],
-    [104, 204, 304, 404, 504, 604],
-    [103, 203, 303, 403, 503, 603],
-    [102, 202, 302, 402, 502, 602],
-    [101, 201, 301, 401, 501, 601],
-    [100, 200, 300, 400, 500, 600],
-];
+// Forward pruning: the deepest remaining depth each kind applies to and its margins,
+// which grow with the depth
+const RFP_DEPTH: u32 = 6;
+const RFP_MARGIN: i32 = 80;
+const RAZOR_DEPTH: u32 = 3;
+const RAZOR_MARGIN: i32 = 250;
+const FUTILITY_DEPTH: u32 = 3;
+const FUTILITY_MARGIN: i32 = 120;
+// Late move pruning leaves the quiet moves alone until 3 + depth^2 moves are searched
+const LMP_DEPTH: u32 = 3;
+const SEE_PRUNING_DEPTH: u32 = 6;
+const SEE_CAPTURE_MARGIN: i32 = 100;
+const SEE_QUIET_MARGIN: i32 = 60;
+
+/// Which forward pruning techniques the search uses; each one has a UCI option to turn
+/// it off, so that what it is worth can be measured
+#[derive(Clone)]
+pub struct Pruning {
+    pub reverse_futility: bool,
+    pub razoring: bool,
+    pub futility: bool,
+    pub late_move: bool,
+    pub see: bool,
+}
+
+impl Default for Pruning {
+    fn default() -> Self {
+        Self {
+            reverse_futility: true,
+            razoring: true,
+            futility: true,
+            late_move: true,
+            see: true,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct SearchInfo {
     // Half move counter
     pub ply: u32,
-    pub nodes: u32,
-    // PV flags
+    pub nodes: u64,
+    // Deepest ply reached in the current iteration
+    pub seldepth: u32,
+    // Whether the current node is still on the PV of the last iteration
     pub follow_pv: bool,
-    pub score_pv: bool,
     // 'Quiet' moves that cause a beta-cutoffs
     pub killer: [[Move; MAX_SEARCH_PLY]; 2], // [id][ply]
     pub history: [[Move; 64]; 12],    // [piece][sq]
+    // 'Quiet' moves that caused a beta-cutoff in reply to a move
+    pub countermove: [[Move; 64]; 12], // [piece][sq] of the move replied to
+    // Static evaluation of the nodes on the current path; None where in check
+    pub static_eval: [Option<i32>; MAX_SEARCH_PLY],
     pub pv_len: [u32; MAX_SEARCH_PLY],
     pub pv_table: [[Move; MAX_SEARCH_PLY]; MAX_SEARCH_PLY],
-    pub tt: Arc<RwLock<HashTT>>,
+    // Undo records of the game moves followed by those of the current search path
+    pub undo_stack: Vec<Undo>,
+    pub tt: Arc<HashTT>,
+    // Number of best lines to search, as set by the 'MultiPV' UCI option
+    pub multi_pv: usize,
+    // Root moves already taken by the earlier lines of a MultiPV iteration
+    pub excluded_root_moves: Vec<Move>,
+    pub pruning: Pruning,
+    // Index of the worker thread using this info; the main worker is 0
+    pub worker_id: usize,
+    pub shared: Arc<SharedSearch>,
 }
 
 impl SearchInfo {
@@ -53,23 +113,33 @@ impl SearchInfo {
         Self {
             ply: 0,
             nodes: 0,
+            seldepth: 0,
             follow_pv: false,
-            score_pv: false,
             killer: [[0; MAX_SEARCH_PLY]; 2],
             history: [[0; 64]; 12],
+            countermove: [[0; 64]; 12],
+            static_eval: [None; MAX_SEARCH_PLY],
             pv_len: [0; MAX_SEARCH_PLY],
             pv_table: [[0; MAX_SEARCH_PLY]; MAX_SEARCH_PLY],
-            tt: Arc::new(RwLock::new(HashTT::new())),
+            undo_stack: vec![],
+            tt: Arc::new(HashTT::default()),
+            multi_pv: 1,
+            excluded_root_moves: vec![],
+            pruning: Pruning::default(),
+            worker_id: 0,
+            shared: Arc::new(SharedSearch::new(1, 0, SearchLimits::default())),
         }
     }
 
     pub fn reset(&mut self) {
         self.ply = 0;
         self.nodes = 0;
+        self.seldepth = 0;
         self.follow_pv = false;
-        self.score_pv = false;
         self.killer = [[0; MAX_SEARCH_PLY]; 2];
         self.history = [[0; 64]; 12];
+        self.countermove = [[0; 64]; 12];
+        self.static_eval = [None; MAX_SEARCH_PLY];
         self.pv_len = [0; MAX_SEARCH_PLY];
         self.pv_table = [[0; MAX_SEARCH_PLY]; MAX_SEARCH_PLY];
     }
@@ -77,118 +147,350 @@ impl SearchInfo {
 
 #[derive(Clone)]
 pub struct SearchData {
-    pub attack_info: AttackInfo,
     pub board: Board,
     pub eval_mask: EvalMasks,
     pub search_info: SearchInfo,
-    pub zobrist_info: ZobristInfo,
     pub uci_state: Arc<RwLock<UCIState>>,
+    // Whether to report diagnostics through 'info string'
+    pub debug: bool,
 }
 
 impl SearchData {
     pub fn from_engine(engine: &Engine) -> Self {
+        let mut search_info = engine.search_info.clone();
+        search_info.undo_stack = engine.undo_stack.clone();
         Self {
-            attack_info: engine.attack_info.clone(),
-            board: engine.board.clone(),
+            board: engine.board,
             eval_mask: engine.eval_mask.clone(),
-            search_info: engine.search_info.clone(),
-            zobrist_info: engine.board.zobrist_info.clone(),
+            search_info,
             uci_state: Arc::clone(&engine.uci_state),
+            debug: engine.debug,
         }
     }
 }
 
-pub fn search_pos(data: &SearchData, depth: u32, thread_count: usize) {
+pub fn search_pos(data: &SearchData, limits: SearchLimits, thread_count: usize) {
+    {
+        let mut info_state = data.uci_state.write().unwrap();
+        info_state.stop = false;
+    }
+    let shared = Arc::new(SharedSearch::new(thread_count, uci::get_curr_time(), limits));
     // 1. Create search worker threads
-    let workers = threads::create_search_workers(data, depth, thread_count);
+    let workers = threads::create_search_workers(data, &shared, thread_count);
     // 2. Join the search worker threads
     for mut worker in workers {
         if let Some(th) = worker.handle.take() {
             let _ = th.join();
         }
     }
+    // In infinite mode the best move may only be sent once the GUI asks for it with 'stop',
+    // the same goes for a ponder search until 'ponderhit' turns it into a normal one
+    loop {
+        let info_state = data.uci_state.read().unwrap();
+        if info_state.stop || !(shared.limits.infinite || info_state.pondering) {
+            break;
+        }
+        drop(info_state);
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    // 3. Report the first move of the deepest completed iteration
+    let best = shared.best.lock().unwrap();
+    if data.debug {
+        let time = uci::get_curr_time() - shared.start_time;
+        println!(
+            "info string {} threads searched {} nodes in {} ms, deepest iteration {}, hashfull {}",
+            thread_count,
+            shared.total_nodes(),
+            time,
+            best.depth,
+            data.search_info.tt.hashfull()
+        );
+    }
+    let best_pv = best.lines.first().map_or(&[][..], |line| &line.pv[..]);
+    let best_move = best_pv.first().copied().or_else(|| {
+        // Stopped before the first iteration finished, so any legal move will do
+        let mut ml = MoveList::new();
+        move_gen::generate_legal(&data.board, &mut ml);
+        let searchmoves = &shared.limits.searchmoves;
+        ml.moves
+            .into_iter()
+            .find(|mv| searchmoves.is_empty() || searchmoves.contains(mv))
+    });
+    match (best_move, ponder_move(data, best_pv)) {
+        (Some(mv), Some(ponder)) => {
+            println!("bestmove {} ponder {}", mv.to_str().trim(), ponder.to_str().trim())
+        }
+        (Some(mv), None) => println!("bestmove {}", mv.to_str().trim()),
+        (None, _) => println!("bestmove 0000"),
+    }
 }
 
-pub fn worker_search_pos(mut data: SearchData, depth: u32, worker_id: usize) {
-    // data.search_info = SearchInfo::new();
-    data.search_info.reset();
-    {
-        let mut info_state = data.uci_state.write().unwrap();
-        info_state.stop = false;
+// The reply we expect to the best move: the second PV move or, if the PV stops short,
+// the hash move of the position after the best move
+fn ponder_move(data: &SearchData, pv: &[Move]) -> Option<Move> {
+    let best_move = *pv.first()?;
+    if let Some(reply) = pv.get(1) {
+        return Some(*reply);
     }
-    let mut alpha = -INFINITY;
-    let mut beta = INFINITY;
-    let start_time = uci::get_curr_time();
+    let mut board = data.board;
+    board.make_move_unchecked(best_move);
+    let reply = data.search_info.tt.probe(&board, 0)?.best_move;
+    (move_gen::is_pseudo_legal(&board, reply) && board.make_move(reply).is_some())
+        .then_some(reply)
+}
 
-    let mut score;
-    'deepen: for current_depth in 1..=depth {
+pub fn worker_search_pos(mut data: SearchData, worker_id: usize) {
+    data.search_info.reset();
+    data.search_info.worker_id = worker_id;
+    let limits = &data.search_info.shared.limits;
+    let depth = limits.depth.unwrap_or(MAX_SEARCH_PLY as u32);
+    let mate = limits.mate;
+    // There can't be more lines than root moves
+    let mut root_moves = MoveList::new();
+    move_gen::generate_legal(&data.board, &mut root_moves);
+    root_moves
+        .moves
+        .retain(|mv| limits.searchmoves.is_empty() || limits.searchmoves.contains(mv));
+    let multi_pv = data.search_info.multi_pv.min(root_moves.moves.len()).max(1);
+    // Lines of the last completed iteration, best first
+    let mut lines: Vec<PvLine> = vec![];
+    let mut progress = SearchProgress::default();
+
+    let mut current_depth = 1;
+    'iterations: while current_depth <= depth {
+        if should_stop(&data.search_info, &data.uci_state) {
+            break;
+        }
+        let iteration_start = uci::get_curr_time();
+        if worker_id != 0 && threads::skip_depth(worker_id, current_depth) {
+            current_depth += 1;
+            continue;
+        }
+        // Each line is the best move left once the ones of the lines before it are excluded
+        let mut new_lines = vec![];
+        data.search_info.excluded_root_moves.clear();
+        data.search_info.seldepth = 0;
+        for pv_index in 0..multi_pv {
+            let previous = lines.get(pv_index);
+            // Follow the PV of the same line in the last iteration
+            let info = &mut data.search_info;
+            // Whatever is left in the root PV belongs to the line searched before this one
+            info.pv_table[0] = [0; MAX_SEARCH_PLY];
+            info.pv_len[0] = 0;
+            if let Some(line) = previous {
+                info.pv_table[0][..line.pv.len()].copy_from_slice(&line.pv);
+                info.pv_len[0] = line.pv.len() as u32;
+            }
+            // Window around the score of the line in the last iteration
+            let (alpha, beta) = previous.map_or((-INFINITY, INFINITY), |line| {
+                (line.score - 50, line.score + 50)
+            });
+            // An interrupted iteration has no usable score or PV
+            let Some(score) = aspiration_search(&mut data, alpha, beta, current_depth) else {
+                break 'iterations;
+            };
+
+            let info = &data.search_info;
+            let pv = extend_pv(
+                &info.tt,
+                data.board,
+                &info.pv_table[0][..info.pv_len[0] as usize],
+                current_depth,
+            );
+            if let Some(mv) = pv.first() {
+                data.search_info.excluded_root_moves.push(*mv);
+            }
+            new_lines.push(PvLine { score, pv });
+        }
+        // A later line can still come out ahead, as every line is a separate search
+        new_lines.sort_by_key(|line| std::cmp::Reverse(line.score));
+        lines = new_lines;
+
+        let info = &data.search_info;
+        info.shared.publish_nodes(worker_id, info.nodes);
+        let result = SearchResult {
+            depth: current_depth,
+            seldepth: info.seldepth,
+            lines: lines.clone(),
+        };
+        info.shared.record_iteration(result, &info.tt);
+        let best = &lines[0];
+        // The clock is only up to the main worker, and only between iterations
+        if worker_id == 0
+            && let Some(best_move) = best.pv.first()
         {
-            if data.uci_state.read().unwrap().stop {
-                break 'deepen;
+            progress.update(*best_move, best.score, uci::get_curr_time() - iteration_start);
+            if !data.uci_state.read().unwrap().should_start_iteration(&progress) {
+                break;
             }
         }
+        // 'go mate' is done as soon as a mate that is short enough turns up
+        if let Some(moves) = mate
+            && best.score > MATE_SCORE
+            && (MATE_VALUE - best.score + 1) / 2 <= moves as i32
+        {
+            break;
+        }
+        current_depth += 1;
+    }
+    data.search_info
+        .shared
+        .publish_nodes(worker_id, data.search_info.nodes);
+    // Once the main worker is done, so are the helpers
+    if worker_id == 0 {
+        data.search_info.shared.finished.store(true, Ordering::Relaxed);
+    }
+}
+
+// Searches the root position, again with a full window whenever the score falls outside
+// the aspiration window. None if the search was stopped before it got a score.
+fn aspiration_search(data: &mut SearchData, alpha: i32, beta: i32, depth: u32) -> Option<i32> {
+    let (mut alpha, mut beta) = (alpha, beta);
+    loop {
         data.search_info.follow_pv = true;
         // Find the best move in the current position
-        score = negamax(
+        let score = negamax(
             &mut data.search_info,
             &mut data.board,
-            &data.attack_info,
             &data.eval_mask,
             &data.uci_state,
-            &data.zobrist_info,
             alpha,
             beta,
-            current_depth,
+            depth,
         );
-        // Aspiration window
-        if (score <= alpha) || (score >= beta) {
-            alpha = -INFINITY;
-            beta = INFINITY;
-            continue;
+        if should_stop(&data.search_info, &data.uci_state) {
+            return None;
+        }
+        if score > alpha && score < beta {
+            return Some(score);
         }
-        // Set up window for next iteration
-        alpha = score - 50;
-        beta = score + 50;
+        alpha = -INFINITY;
+        beta = INFINITY;
+    }
+}
 
-        let time_diff = uci::get_curr_time() - start_time;
+// The PV table loses the tail of a line wherever a hash hit ended it early, so carry
+// on with the moves stored in the TT for as long as they are legal
+fn extend_pv(tt: &HashTT, mut board: Board, pv: &[Move], depth: u32) -> Vec<Move> {
+    let mut line = pv.to_vec();
+    for mv in pv {
+        board.make_move_unchecked(*mv);
+    }
+    while line.len() < depth as usize {
+        let Some(entry) = tt.probe(&board, 0) else {
+            break;
+        };
+        if !move_gen::is_pseudo_legal(&board, entry.best_move)
+            || board.make_move(entry.best_move).is_none()
+        {
+            break;
+        }
+        line.push(entry.best_move);
+    }
+    line
+}
 
-        if worker_id != 0 {
-            continue;
+pub fn print_info(shared: &SharedSearch, result: &SearchResult, hashfull: u32) {
+    let time = uci::get_curr_time() - shared.start_time;
+    let nodes = shared.total_nodes();
+    let nps = nodes * 1000 / time.max(1) as u64;
+    for (pv_index, line) in result.lines.iter().enumerate() {
+        let score = line.score;
+        let (cp_str, cp_score) = if score > -MATE_VALUE && score < -MATE_SCORE {
+            ("mate", (-(score + MATE_VALUE) / 2) - 1)
+        } else if score > MATE_SCORE && score < MATE_VALUE {
+            ("mate", ((MATE_VALUE - score) / 2) + 1)
+        } else {
+            ("cp", score)
+        };
+        print!(
+            "info multipv {} score {} {} depth {} seldepth {} nodes {} nps {} hashfull {} time {} pv ",
+            pv_index + 1,
+            cp_str,
+            cp_score,
+            result.depth,
+            result.seldepth,
+            nodes,
+            nps,
+            hashfull,
+            time
+        );
+        // Print principal variation
+        for mv in &line.pv {
+            print!("{} ", mv.to_str().trim());
         }
-        // Only the first worker thread should print these information
-        if data.search_info.pv_len[0] != 0 {
-            let (cp_str, cp_score) = if score > -MATE_VALUE && score < -MATE_SCORE {
-                ("mate", (-(score + MATE_VALUE) / 2) - 1)
-            } else if score > MATE_SCORE && score < MATE_VALUE {
-                ("mate", ((MATE_VALUE - score) / 2) + 1)
-            } else {
-                ("cp", score)
-            };
-            print!(
-                "info score {} {} depth {} nodes {} time {} pv ",
-                cp_str, cp_score, current_depth, data.search_info.nodes, time_diff
-            );
-            // Print principal variation
-            for i in 0..(data.search_info.pv_len[0] as usize) {
-                print!("{} ", data.search_info.pv_table[0][i].to_str().trim());
-            }
-            println!();
+        println!();
+    }
+}
+
+const CHECK_UP_NODES: u64 = 2047;
+// How long a search runs before the root moves are reported as they are searched
+const CURRMOVE_DELAY: u128 = 3000;
+
+// Communicates with UCI every so often and enforces the node budget
+fn check_up(info: &SearchInfo, uci_state: &Arc<RwLock<UCIState>>) {
+    let node_limit = info.shared.limits.nodes;
+    if (info.nodes & CHECK_UP_NODES) == 0 {
+        info.shared.publish_nodes(info.worker_id, info.nodes);
+        let mut info_state = uci_state.write().unwrap();
+        // The clock can't stop the search before there is a move to show for it
+        if info.shared.has_result() {
+            info_state.check_up();
+        }
+        if node_limit.is_some_and(|limit| info.shared.total_nodes() >= limit) {
+            info_state.stop = true;
         }
     }
-    if worker_id == 0 {
-        println!("bestmove {}", data.search_info.pv_table[0][0].to_str().trim());
+    // Every worker on its own has to stay within the budget too; with a single thread
+    // that stops the search right at the limit
+    if node_limit.is_some_and(|limit| info.nodes >= limit) {
+        uci_state.write().unwrap().stop = true;
+    }
+}
+
+// Once the search has been running for a while, tells the GUI which root move it's on
+fn print_currmove(info: &SearchInfo, depth: u32, mv: Move, move_number: usize) {
+    if uci::get_curr_time() - info.shared.start_time >= CURRMOVE_DELAY {
+        println!(
+            "info depth {} currmove {} currmovenumber {}",
+            depth,
+            mv.to_str().trim(),
+            move_number
+        );
+    }
+}
+
+// Whether the search has to be abandoned: by the GUI, the clock, the node budget or,
+// for helpers, the main worker being done
+// Plays a move and records it on the search path; false if the move is illegal
+fn make_move(info: &mut SearchInfo, board: &mut Board, mv: Move) -> bool {
+    match board.make_move(mv) {
+        Some(undo) => {
+            info.undo_stack.push(undo);
+            true
+        }
+        None => false,
     }
 }
 
-const CHECK_UP_NODES: u32 = 2047;
+// Takes back the last move played with `make_move`
+fn unmake_move(info: &mut SearchInfo, board: &mut Board) {
+    let undo = info
+        .undo_stack
+        .pop()
+        .expect("unmake_move() called without a matching make_move()");
+    board.unmake_move(undo);
+}
+
+fn should_stop(info: &SearchInfo, uci_state: &Arc<RwLock<UCIState>>) -> bool {
+    info.shared.finished.load(Ordering::Relaxed) || uci_state.read().unwrap().stop
+}
 
 fn negamax(
     info: &mut SearchInfo,
     board: &mut Board,
-    attack_info: &AttackInfo,
     mask: &EvalMasks,
     uci_state: &Arc<RwLock<UCIState>>,
-    zobrist_info: &ZobristInfo,
     mut alpha: i32,
     beta: i32,
     mut depth: u32,
@@ -196,135 +498,209 @@ fn negamax(
     info.pv_len[info.ply as usize] = info.ply;
     // Store the current move's score
     let mut score;
-    let mut tt_flag = TTFlag::Exact;
-    // Repetition stuff
+    let mut tt_flag = TTFlag::Alpha;
     let is_pv_node = (beta - alpha) > 1;
+    // With some root moves left out (by MultiPV or 'go searchmoves') the root score isn't
+    // the position's score
+    let store_tt = info.ply != 0
+        || (info.excluded_root_moves.is_empty() && info.shared.limits.searchmoves.is_empty());
+
+    // Repeated positions and positions past the fifty move rule are draws
+    if info.ply != 0
+        && (board.is_repetition(&info.undo_stack, info.ply as usize) || board.is_fifty_move_draw())
+    {
+        return 0;
+    }
 
     // If score of current position exists, return score instead of searching
     // Read hash entry (if not root ply) score for current position and isn't PV node
-    #[allow(unused_assignments)]
-    let mut hash_score = None;
+    let tt_entry = info.tt.probe(board, info.ply);
+    if let Some(score) = tt_entry.and_then(|entry| entry.cutoff(alpha, beta, depth))
+        && info.ply != 0
+        && !is_pv_node
     {
-        let mut info_tt = info.tt.write().unwrap();
-        hash_score = info_tt.read_entry(board, alpha, beta, depth, info.ply);
-    }
-    if info.ply != 0 && hash_score.is_some() && !is_pv_node {
-        return hash_score.unwrap();
+        return score;
     }
+    // Even without a cutoff the stored move is the best guess at what to search first.
+    // The move picker checks it against the position, so a colliding entry can't
+    // slip an illegal move in.
+    let hash_move = tt_entry.map_or(0, |entry| entry.best_move);
 
-    // Communicate with UCI every so often
-    if (info.nodes & CHECK_UP_NODES) == 0 {
-        let mut info_state = uci_state.write().unwrap();
-        info_state.check_up();
-    }
+    check_up(info, uci_state);
 
     // Escape condition or Base case
     if depth == 0 {
-        return quiescence(info, board, attack_info, mask, uci_state, zobrist_info, alpha, beta);
+        return quiescence(info, board, mask, uci_state, alpha, beta);
     }
     // Exit if ply > max ply; ply should be <= 63
     if info.ply > (MAX_SEARCH_PLY - 1) as u32 {
-        return eval::evaluate(&board.pos, board.state.side, attack_info, mask);
+        return eval::evaluate(&board.pos, board.state.side, mask);
     }
     // Increment nodes
     info.nodes += 1;
+    info.seldepth = info.seldepth.max(info.ply);
 
     // Check extension
-    let in_check =  board::in_check(board, attack_info, board.state.xside);
+    let in_check = board::in_check(board, board.state.xside);
     if in_check {
         depth += 1;
     }
-    let mut legal_move_count = 0;
+
+    // Static evaluation based pruning is only safe away from the PV and out of check
+    let can_prune = !is_pv_node && !in_check && info.ply != 0;
+    let static_eval = if in_check {
+        None
+    } else {
+        Some(eval::evaluate(&board.pos, board.state.side, mask))
+    };
+    info.static_eval[info.ply as usize] = static_eval;
+    // Whether the position got better for the side to move since its last turn
+    let improving = match (static_eval, info.ply.checked_sub(2)) {
+        (Some(eval), Some(ply)) => info.static_eval[ply as usize].is_some_and(|prev| eval > prev),
+        _ => false,
+    };
+    let static_eval = static_eval.unwrap_or(0);
+    if can_prune {
+        // Reverse futility pruning: so far above beta that the remaining depth
+        // is unlikely to bring the score back down
+        if info.pruning.reverse_futility
+            && depth <= RFP_DEPTH
+            && beta.abs() < MATE_SCORE
+            && static_eval - RFP_MARGIN * depth as i32 >= beta
+        {
+            return beta;
+        }
+        // Razoring: so far below alpha that only captures could help, so let
+        // quiescence search decide
+        if info.pruning.razoring
+            && depth <= RAZOR_DEPTH
+            && alpha.abs() < MATE_SCORE
+            && static_eval + RAZOR_MARGIN * (depth as i32) <= alpha
+        {
+            score = quiescence(info, board, mask, uci_state, alpha, beta);
+            if score <= alpha {
+                return alpha;
+            }
+        }
+    }
+    // Futility pruning: quiet moves can't make up the distance to alpha this close
+    // to the horizon
+    let futile = can_prune
+        && info.pruning.futility
+        && depth <= FUTILITY_DEPTH
+        && static_eval + FUTILITY_MARGIN * (depth as i32) <= alpha;
 
     // NULL move pruning
     if depth >= 3 && !in_check && info.ply != 0 {
-        let clone = board.clone();
         info.ply += 1;
-        // Repetition stuff
-        if board.state.enpassant != Sq::NoSq {
-            zobrist::update(ZobristAction::Enpassant, board);
-        }
-        board.state.enpassant = Sq::NoSq;
-        board.state.change_side();
-        zobrist::update(ZobristAction::ChangeColor, board);
+        let undo = board.make_null_move();
+        info.undo_stack.push(undo);
         // Search move with reduced depth to find beta-cutoffs
         score = -negamax(
             info,
             board,
-            attack_info,
             mask,
             uci_state,
-            zobrist_info,
             -beta,
             -beta + 1,
             depth - 1 - 2,
         );
         info.ply -= 1;
-        *board = clone;
+        info.undo_stack.pop();
+        board.unmake_null_move(undo);
         // When timer runs out, return 0
-        {
-            if uci_state.read().unwrap().stop {
-                return 0;
-            }
+        if should_stop(info, uci_state) {
+            return 0;
         }
         // Fail hard; beta-cutoffs
         if score >= beta {
             return beta;
         }
     }
-    let mut ml = MoveList::new();
-    move_gen::generate(board, attack_info, &mut ml);
-    if info.follow_pv {
-        enable_pv_scoring(info, &mut ml);
-    }
-    sort_moves(info, board, &mut ml);
+    // Along the PV of the last iteration its move goes first, everywhere else the hash move
+    let pv_move = info.pv_table[0][info.ply as usize];
+    info.follow_pv = info.follow_pv && move_gen::is_pseudo_legal(board, pv_move);
+    let first_move = if info.follow_pv { pv_move } else { hash_move };
+    let mut picker = MovePicker::new(info, first_move);
 
-    let mut clone;
+    let mut best_move = 0;
     let mut move_searched = 0;
-    for mv in &ml.moves {
-        // Preserve board state by copying it
-        clone = board.clone();
-        info.ply += 1;
-        // Repetition stuff
+    while let Some(mv) = picker.next(info, board) {
+        if info.ply == 0 {
+            let searchmoves = &info.shared.limits.searchmoves;
+            if (!searchmoves.is_empty() && !searchmoves.contains(&mv))
+                || info.excluded_root_moves.contains(&mv)
+            {
+                continue;
+            }
+        }
+        let is_quiet = !mv.is_capture() && mv.promoted().is_none();
+        // Never before a move has been searched, so mates and stalemates are still
+        // told apart, nor while getting mated
+        let prune_move = can_prune && move_searched > 0 && alpha > -MATE_SCORE;
+        // SEE pruning: moves that lose too much material on their target square
+        if prune_move && info.pruning.see && depth <= SEE_PRUNING_DEPTH {
+            let margin = if is_quiet {
+                SEE_QUIET_MARGIN
+            } else {
+                SEE_CAPTURE_MARGIN
+            };
+            if !board.see_ge(mv, -margin * depth as i32) {
+                continue;
+            }
+        }
         // Make sure that every move from this point on is legal
-        if !moves::play(board, attack_info, *mv, MoveFlag::AllMoves) {
-            info.ply -= 1;
-            // Repetition stuff
+        if !make_move(info, board, mv) {
             continue;
         }
-        legal_move_count += 1;
+        // Futility and late move pruning of quiet moves that don't give check
+        if prune_move && is_quiet && !board::in_check(board, board.state.xside) {
+            let late = info.pruning.late_move
+                && depth <= LMP_DEPTH
+                && move_searched >= 3 + (depth * depth) as usize;
+            if futile || late {
+                unmake_move(info, board);
+                continue;
+            }
+        }
+        if info.ply == 0 && info.worker_id == 0 {
+            // Moves taken by earlier MultiPV lines count as searched already
+            let move_number = info.excluded_root_moves.len() + move_searched + 1;
+            print_currmove(info, depth, mv, move_number);
+        }
+        info.ply += 1;
 
         // Full depth search
         if move_searched == 0 {
             score = -negamax(
                 info,
                 board,
-                attack_info,
                 mask,
                 uci_state,
-                zobrist_info,
                 -beta,
                 -alpha,
                 depth - 1,
             );
         } else {
             // Late move reduction (LMR)
-            if move_searched >= FULL_DEPTH_MOVES
+            let reduction = if move_searched >= FULL_DEPTH_MOVES
                 && depth >= REDUCTION_LIMIT
                 && !in_check
-                && mv.promoted().is_none()
-                && !mv.is_capture()
+                && is_quiet
             {
+                lmr_reduction(info, depth, move_searched, mv, is_pv_node, improving)
+            } else {
+                0
+            };
+            if reduction > 0 {
                 score = -negamax(
                     info,
                     board,
-                    attack_info,
                     mask,
                     uci_state,
-                    zobrist_info,
                     -alpha - 1,
                     -alpha,
-                    depth - 2,
+                    depth - 1 - reduction,
                 );
             } else {
                 // Hack to ensure that full depth search is done
@@ -336,10 +712,8 @@ fn negamax(
                 score = -negamax(
                     info,
                     board,
-                    attack_info,
                     mask,
                     uci_state,
-                    zobrist_info,
                     -alpha - 1,
                     -alpha,
                     depth - 1,
@@ -349,10 +723,8 @@ fn negamax(
                     score = -negamax(
                         info,
                         board,
-                        attack_info,
                         mask,
                         uci_state,
-                        zobrist_info,
                         -beta,
                         -alpha,
                         depth - 1,
@@ -361,15 +733,14 @@ fn negamax(
             }
         }
         info.ply -= 1;
-        // Repetition stuff
-        *board = clone;
+        unmake_move(info, board);
+        move_searched += 1;
+        // Only the first move searched can continue the PV
+        info.follow_pv = false;
         // When timer runs out, return 0
-        {
-            if uci_state.read().unwrap().stop {
-                return 0;
-            }
+        if should_stop(info, uci_state) {
+            return 0;
         }
-        move_searched += 1;
 
         // If a better move is found
         if score > alpha {
@@ -381,9 +752,10 @@ fn negamax(
 
             // PV node
             alpha = score;
+            best_move = mv;
 
             // Write PV move
-            info.pv_table[info.ply as usize][info.ply as usize] = *mv;
+            info.pv_table[info.ply as usize][info.ply as usize] = mv;
 
             // Copy PV from following plies
             for next_ply in (info.ply + 1)..info.pv_len[info.ply as usize + 1] {
@@ -395,21 +767,26 @@ fn negamax(
 
             // Fail hard; beta-cutoff
             if score >= beta {
-                {
-                    let mut info_tt = info.tt.write().unwrap();
-                    info_tt.write_entry(board, depth, beta, TTFlag::Beta, info.ply);
+                if store_tt {
+                    info.tt.write_entry(board, depth, beta, TTFlag::Beta, mv, info.ply);
                 }
 
                 if !mv.is_capture() {
                     info.killer[1][info.ply as usize] = info.killer[0][info.ply as usize];
-                    info.killer[0][info.ply as usize] = *mv;
+                    info.killer[0][info.ply as usize] = mv;
+                    // No countermove after a null move
+                    if let Some(prev) = info.undo_stack.last().map(|undo| undo.mv)
+                        && prev != 0
+                    {
+                        info.countermove[prev.piece() as usize][prev.target() as usize] = mv;
+                    }
                 }
                 // Node (move) fails high
                 return beta;
             }
         }
     }
-    if legal_move_count == 0 {
+    if move_searched == 0 {
         // Possible checkmate or stalemate
         if in_check {
             // Mating score
@@ -422,33 +799,56 @@ fn negamax(
         }
     }
 
-    {
-        let mut info_tt = info.tt.write().unwrap();
-        info_tt.write_entry(board, depth, alpha, tt_flag, info.ply);
+    if store_tt {
+        info.tt.write_entry(board, depth, alpha, tt_flag, best_move, info.ply);
     }
     // Node (move) that fails low
     alpha
 }
 
+// How many plies less than the full depth a late quiet move is searched with. Moves of PV
+// nodes, killers and moves with a good history are searched deeper, moves of positions
+// that aren't improving shallower. The search always keeps at least one ply.
+fn lmr_reduction(
+    info: &SearchInfo,
+    depth: u32,
+    move_searched: usize,
+    mv: Move,
+    is_pv_node: bool,
+    improving: bool,
+) -> u32 {
+    let ply = info.ply as usize;
+    let mut reduction = LMR_TABLE[(depth as usize).min(MAX_SEARCH_PLY - 1)]
+        [move_searched.min(LMR_MOVES - 1)];
+    if is_pv_node {
+        reduction -= 1;
+    }
+    if !improving {
+        reduction += 1;
+    }
+    if mv == info.killer[0][ply] || mv == info.killer[1][ply] {
+        reduction -= 1;
+    }
+    let history = info.history[mv.piece() as usize][mv.target() as usize];
+    reduction -= (history / LMR_HISTORY_DIVISOR).min(2) as i32;
+    // Always leave at least one ply to search; `clamp` would panic below depth 2
+    reduction.min(depth as i32 - 2).max(0) as u32
+}
+
 pub fn quiescence(
     info: &mut SearchInfo,
     board: &mut Board,
-    attack_info: &AttackInfo,
     mask: &EvalMasks,
     uci_state: &Arc<RwLock<UCIState>>,
-    zobrist_info: &ZobristInfo,
     mut alpha: i32,
     beta: i32,
 ) -> i32 {
-    // Communicate with UCI every so often
-    if (info.nodes & CHECK_UP_NODES) == 0 {
-        let mut info_state = uci_state.write().unwrap();
-        info_state.check_up();
-    }
+    check_up(info, uci_state);
 
     info.nodes += 1;
+    info.seldepth = info.seldepth.max(info.ply);
     // Escape condition
-    let eval = eval::evaluate(&board.pos, board.state.side, attack_info, mask);
+    let eval = eval::evaluate(&board.pos, board.state.side, mask);
     // Exit if ply > max ply; ply should be <= 63
     if info.ply > MAX_SEARCH_PLY as u32 - 1 {
         return eval;
@@ -464,31 +864,23 @@ pub fn quiescence(
         alpha = eval;
     }
 
-    let mut ml = MoveList::new();
-    move_gen::generate(board, attack_info, &mut ml);
-    sort_moves(info, board, &mut ml);
-
-    let mut clone;
-    for mv in &ml.moves {
-        clone = board.clone();
-        info.ply += 1;
-        // Repetition stuff
+    let mut picker = MovePicker::new_quiescence();
+    while let Some(mv) = picker.next(info, board) {
+        // Captures that lose material can't improve on standing pat
+        if info.pruning.see && !board.see_ge(mv, 0) {
+            continue;
+        }
         // Make sure that every move from this point on is legal
-        if !moves::play(board, attack_info, *mv, MoveFlag::CapturesOnly) {
-            info.ply -= 1;
-            // Repetition stuff
+        if !make_move(info, board, mv) {
             continue;
         }
-        let score = -quiescence(info, board, attack_info, mask, uci_state, zobrist_info, -beta, -alpha);
+        info.ply += 1;
+        let score = -quiescence(info, board, mask, uci_state, -beta, -alpha);
         info.ply -= 1;
-        // Repetition stuff
-        *board = clone;
+        unmake_move(info, board);
         // When timer runs out, return 0
-
-        {
-            if uci_state.read().unwrap().stop {
-                return 0;
-            }
+        if should_stop(info, uci_state) {
+            return 0;
         }
         if score > alpha {
             // PV node
@@ -502,76 +894,60 @@ pub fn quiescence(
     alpha
 }
 
-fn score_move(info: &mut SearchInfo, board: &mut Board, mv: Move) -> u32 {
-    if info.score_pv {
-        // Check if move on current ply is a PV move
-        if info.pv_table[0][info.ply as usize] == mv {
-            info.score_pv = false;
-            // Give PV move the highest score so as to search it first
-            return 20_000;
-        }
-    }
-
-    if mv.is_capture() {
-        // Set to pawn by default; for enpassant
-        let mut captured = Piece::LP as usize;
-        // Get opponent piece start and end range
-        let (start, end) = if board.state.side == PieceColor::Light {
-            (Piece::DP as usize, Piece::DK as usize)
-        } else {
-            (Piece::LP as usize, Piece::LK as usize)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::fen::FEN_POSITIONS;
+
+    // Nodes and best line of a single threaded fixed depth search
+    fn search(fen: &str, depth: u32, pruning: Pruning) -> (u64, PvLine) {
+        let mut engine = Engine::new();
+        engine.board.set_fen(fen).unwrap();
+        engine.search_info.pruning = pruning;
+        let mut data = SearchData::from_engine(&engine);
+        let limits = SearchLimits {
+            depth: Some(depth),
+            ..SearchLimits::default()
         };
-        for i in start..=end {
-            if board.pos.piece[i].get(mv.target() as usize) {
-                captured = i;
-                break;
-            }
-        }
-        // Add 10,000 to ensure captures are evaluated before killer moves
-        MVV_LVA[(mv.piece() as usize) % 6][captured % 6] + 10_000
-    } else {
-        // Score the best killer move
-        if info.killer[0][info.ply as usize] == mv {
-            9000
-        } else if info.killer[1][info.ply as usize] == mv {
-            return 8000;
-        } else {
-            return info.history[mv.piece() as usize][mv.target() as usize];
-        }
+        let shared = Arc::new(SharedSearch::new(1, uci::get_curr_time(), limits));
+        data.search_info.shared = Arc::clone(&shared);
+        worker_search_pos(data, 0);
+        let best = shared.best.lock().unwrap();
+        (shared.total_nodes(), best.lines[0].clone())
     }
-}
 
-fn sort_moves(info: &mut SearchInfo, board: &mut Board, ml: &mut MoveList) {
-    let mut move_score_list: Vec<u32> = vec![];
-    for mv in &ml.moves {
-        move_score_list.push(score_move(info, board, *mv));
-    }
-
-    // Sort moves and their scores in 'descending' order
-    for curr in 0..ml.moves.len() {
-        for next in (curr + 1)..ml.moves.len() {
-            if move_score_list[curr] < move_score_list[next] {
-                // Swap scores
-                move_score_list.swap(curr, next);
-                /* let temp = move_score_list[curr];
-                move_score_list[curr] = move_score_list[next];
-                move_score_list[next] = temp; */
-                // Swap moves
-                ml.moves.swap(curr, next);
-                /* let temp = ml.moves[curr];
-                ml.moves[curr] = ml.moves[next];
-                ml.moves[next] = temp; */
-            }
+    #[test]
+    fn each_pruning_toggle_turns_its_pruning_off() {
+        let toggles: [fn(&mut Pruning); 5] = [
+            |pruning| pruning.reverse_futility = false,
+            |pruning| pruning.razoring = false,
+            |pruning| pruning.futility = false,
+            |pruning| pruning.late_move = false,
+            |pruning| pruning.see = false,
+        ];
+        let (all_on, _) = search(FEN_POSITIONS[2], 7, Pruning::default());
+        for (i, toggle) in toggles.iter().enumerate() {
+            let mut pruning = Pruning::default();
+            toggle(&mut pruning);
+            let (nodes, _) = search(FEN_POSITIONS[2], 7, pruning);
+            assert_ne!(nodes, all_on, "toggle {}", i);
         }
     }
-}
 
-fn enable_pv_scoring(info: &mut SearchInfo, ml: &mut MoveList) {
-    info.follow_pv = false;
-    for i in 0..ml.moves.len() {
-        if info.pv_table[0][info.ply as usize] == ml.moves[i] {
-            info.score_pv = true;
-            info.follow_pv = true;
+    #[test]
+    fn pruning_still_finds_a_mate() {
+        // Rb7+ drives the king to the back rank, where Ra8 mates
+        let fen = "8/7k/R7/8/8/8/8/1R4K1 w - - 0 1";
+        let none = Pruning {
+            reverse_futility: false,
+            razoring: false,
+            futility: false,
+            late_move: false,
+            see: false,
+        };
+        for pruning in [none, Pruning::default()] {
+            let (_, line) = search(fen, 6, pruning);
+            assert_eq!(line.score, MATE_VALUE - 3);
         }
     }
 }
@@ -1,28 +1,117 @@
 use crate::engine::Engine;
 use crate::search::{self, SearchData};
+use crate::tt::HashTT;
+use crate::uci::SearchLimits;
+use chess::moves::Move;
 
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
 
+// Helper threads skip some iterations so they don't all search the same depth at the
+// same time; the skip pattern repeats every 20 helpers
+const SKIP_SIZE: [u32; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [u32; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
 pub struct WorkerThread {
     pub handle: Option<JoinHandle<()>>,
 }
 
-pub fn launch_search_thread(engine: &mut Engine, depth: u32) {
+#[derive(Clone)]
+pub struct PvLine {
+    pub score: i32,
+    pub pv: Vec<Move>,
+}
+
+// Deepest fully searched iteration of any worker
+#[derive(Default)]
+pub struct SearchResult {
+    pub depth: u32,
+    // Deepest ply reached, quiescence included
+    pub seldepth: u32,
+    // One line per MultiPV, best first
+    pub lines: Vec<PvLine>,
+}
+
+/// Everything the workers of one search share apart from the transposition table
+pub struct SharedSearch {
+    // Node count of each worker, published every so often
+    nodes: Vec<AtomicU64>,
+    pub best: Mutex<SearchResult>,
+    pub start_time: u128,
+    pub limits: SearchLimits,
+    // Set once the main worker is done, which is the helpers' cue to stop as well
+    pub finished: AtomicBool,
+}
+
+impl SharedSearch {
+    pub fn new(thread_count: usize, start_time: u128, limits: SearchLimits) -> Self {
+        Self {
+            nodes: (0..thread_count).map(|_| AtomicU64::new(0)).collect(),
+            best: Mutex::new(SearchResult::default()),
+            start_time,
+            limits,
+            finished: AtomicBool::new(false),
+        }
+    }
+
+    pub fn publish_nodes(&self, worker_id: usize, nodes: u64) {
+        if let Some(count) = self.nodes.get(worker_id) {
+            count.store(nodes, Ordering::Relaxed);
+        }
+    }
+
+    pub fn total_nodes(&self) -> u64 {
+        self.nodes
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn has_result(&self) -> bool {
+        self.best.lock().unwrap().depth > 0
+    }
+
+    /// Keeps the iteration if it's deeper than every iteration completed so far
+    /// and reports it to the GUI
+    pub fn record_iteration(&self, result: SearchResult, tt: &HashTT) {
+        let mut best = self.best.lock().unwrap();
+        if result.depth <= best.depth {
+            return;
+        }
+        *best = result;
+        // Printed while holding the lock, so the lines from different workers stay in order
+        search::print_info(self, &best, tt.hashfull());
+    }
+}
+
+/// Whether a helper thread (id > 0) should skip this iteration
+pub fn skip_depth(worker_id: usize, depth: u32) -> bool {
+    let i = (worker_id - 1) % SKIP_SIZE.len();
+    !((depth + SKIP_PHASE[i]) / SKIP_SIZE[i]).is_multiple_of(2)
+}
+
+pub fn launch_search_thread(engine: &mut Engine, limits: SearchLimits) {
     let data = SearchData::from_engine(engine);
     let worker_thread_count = engine.worker_thread_count;
     let th = std::thread::spawn(move || {
-        search::search_pos(&data, depth, worker_thread_count);
+        search::search_pos(&data, limits, worker_thread_count);
     });
 
     engine.search_thread = Some(th);
 }
 
-pub fn create_search_workers(data: &SearchData, depth: u32, thread_count: usize) -> Vec<WorkerThread> {
+pub fn create_search_workers(
+    data: &SearchData,
+    shared: &Arc<SharedSearch>,
+    thread_count: usize,
+) -> Vec<WorkerThread> {
     let mut workers = vec![];
     for i in 0..thread_count {
-        let data = data.clone();
+        let mut data = data.clone();
+        data.search_info.shared = Arc::clone(shared);
         let th = std::thread::spawn(move || {
-            search::worker_search_pos(data, depth, i);
+            search::worker_search_pos(data, i);
         });
         let worker = WorkerThread {
             handle: Some(th)
@@ -31,3 +120,53 @@ pub fn create_search_workers(data: &SearchData, depth: u32, thread_count: usize)
     }
     workers
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tt::MIN_HASH_MB;
+
+    fn skipped(worker_id: usize) -> Vec<u32> {
+        (1..=12).filter(|&depth| skip_depth(worker_id, depth)).collect()
+    }
+
+    #[test]
+    fn helpers_skip_staggered_depths() {
+        assert_eq!(skipped(1), [1, 3, 5, 7, 9, 11]);
+        assert_eq!(skipped(2), [2, 4, 6, 8, 10, 12]);
+        assert_eq!(skipped(3), [2, 3, 6, 7, 10, 11]);
+        assert_eq!(skipped(4), [1, 2, 5, 6, 9, 10]);
+        assert_eq!(skipped(7), [3, 4, 5, 9, 10, 11]);
+        assert_eq!(skipped(13), [4, 5, 6, 7, 12]);
+        assert_eq!(skipped(20), [5, 6, 7, 8]);
+        // The pattern starts over after 20 helpers
+        assert_eq!(skipped(21), skipped(1));
+        assert_eq!(skipped(40), skipped(20));
+    }
+
+    fn result(depth: u32, score: i32) -> SearchResult {
+        SearchResult {
+            depth,
+            seldepth: depth,
+            lines: vec![PvLine { score, pv: vec![] }],
+        }
+    }
+
+    #[test]
+    fn only_deeper_iterations_are_recorded() {
+        let shared = SharedSearch::new(2, 0, SearchLimits::default());
+        let tt = HashTT::new(MIN_HASH_MB);
+        assert!(!shared.has_result());
+        shared.record_iteration(result(5, 10), &tt);
+        // A helper that finishes a shallower or equally deep iteration later changes nothing
+        shared.record_iteration(result(3, 20), &tt);
+        shared.record_iteration(result(5, 30), &tt);
+        {
+            let best = shared.best.lock().unwrap();
+            assert_eq!((best.depth, best.lines[0].score), (5, 10));
+        }
+        shared.record_iteration(result(6, 40), &tt);
+        let best = shared.best.lock().unwrap();
+        assert_eq!((best.depth, best.lines[0].score), (6, 40));
+    }
+}
@@ -0,0 +1,243 @@
+use chess::consts::PieceColor;
+use chess::moves::Move;
+use crate::uci::{self, SearchLimits};
+
+// Limits of the 'Move Overhead' UCI option in milliseconds: time set aside on every move
+// for the GUI and the connection, so the engine doesn't lose on time because of the lag
+pub const DEFAULT_MOVE_OVERHEAD: u32 = 30;
+pub const MAX_MOVE_OVERHEAD: u32 = 5000;
+
+// Moves the remaining time is spread over when the GUI doesn't say
+const DEFAULT_MOVES_TO_GO: u32 = 40;
+// An iteration usually takes about this many times as long as the one before it
+const ITERATION_GROWTH: u128 = 2;
+
+/// Time budget of one search. The optimum is what the search aims for and is only looked
+/// at between iterations; the maximum aborts the search wherever it is.
+#[derive(Clone, Debug)]
+pub struct TimeManager {
+    pub start_time: u128,
+    pub optimum: u128,
+    pub maximum: u128,
+}
+
+impl TimeManager {
+    /// Works out the budget for the side to move, or None if the search isn't timed
+    pub fn new(
+        limits: &SearchLimits,
+        side: PieceColor,
+        move_overhead: u32,
+        start_time: u128,
+    ) -> Option<Self> {
+        if limits.infinite {
+            return None;
+        }
+        let overhead = move_overhead as u128;
+        let (optimum, maximum) = if let Some(movetime) = limits.movetime {
+            // A fixed time per move has nothing to adjust
+            let budget = (movetime as u128).saturating_sub(overhead).max(1);
+            (budget, budget)
+        } else {
+            let (time, increment) = limits.clock(side);
+            let available = (time? as u128).saturating_sub(overhead).max(1);
+            let moves_to_go = limits
+                .movestogo
+                .unwrap_or(DEFAULT_MOVES_TO_GO)
+                .clamp(1, DEFAULT_MOVES_TO_GO) as u128;
+            // Most of the increment comes back after the move, so it can be spent right away
+            let optimum = (available / moves_to_go + increment as u128 * 3 / 4)
+                .min(available * 3 / 4)
+                .max(1);
+            let maximum = (optimum * 4).min(available * 4 / 5).max(optimum);
+            (optimum, maximum)
+        };
+        Some(Self {
+            start_time,
+            optimum,
+            maximum,
+        })
+    }
+
+    pub fn elapsed(&self) -> u128 {
+        uci::get_curr_time().saturating_sub(self.start_time)
+    }
+
+    pub fn out_of_time(&self) -> bool {
+        self.elapsed() >= self.maximum
+    }
+
+    /// Whether another iteration is likely to finish within the optimum, as adjusted by
+    /// what the iterations so far have shown. One that can't is better not started at all
+    /// than cut off by the maximum with nothing to show for it.
+    pub fn should_start_iteration(&self, progress: &SearchProgress) -> bool {
+        let target = (self.optimum * progress.time_scale() / 100).min(self.maximum);
+        self.elapsed() + progress.last_iteration_time * ITERATION_GROWTH <= target
+    }
+}
+
+/// What the main worker's iterations have shown so far
+#[derive(Default)]
+pub struct SearchProgress {
+    best_move: Move,
+    // Iterations in a row that ended with the same best move
+    stable_iterations: u32,
+    score: Option<i32>,
+    // How far the score fell in the last iteration
+    score_drop: i32,
+    last_iteration_time: u128,
+}
+
+impl SearchProgress {
+    pub fn update(&mut self, best_move: Move, score: i32, iteration_time: u128) {
+        if best_move == self.best_move {
+            self.stable_iterations += 1;
+        } else {
+            self.best_move = best_move;
+            self.stable_iterations = 0;
+        }
+        self.score_drop = self.score.map_or(0, |prev| prev - score);
+        self.score = Some(score);
+        self.last_iteration_time = iteration_time;
+    }
+
+    // Percentage of the optimum time the search should take
+    fn time_scale(&self) -> u128 {
+        // A best move that keeps changing needs more time to settle, a steady one less
+        let stability = match self.stable_iterations {
+            0 => 130,
+            1 => 115,
+            2 => 100,
+            3 => 90,
+            _ => 80,
+        };
+        // A falling score needs more time too; up to twice as much once it drops a pawn
+        let score_drop = 100 + self.score_drop.clamp(0, 100) as u128;
+        stability * score_drop / 100
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(limits: SearchLimits, side: PieceColor, move_overhead: u32) -> (u128, u128) {
+        let time = TimeManager::new(&limits, side, move_overhead, 0).unwrap();
+        (time.optimum, time.maximum)
+    }
+
+    #[test]
+    fn movetime_is_the_whole_budget() {
+        let limits = SearchLimits {
+            movetime: Some(1000),
+            wtime: Some(60000),
+            ..SearchLimits::default()
+        };
+        assert_eq!(budget(limits, PieceColor::Light, 30), (970, 970));
+    }
+
+    #[test]
+    fn clock_is_spread_over_moves_to_go() {
+        let limits = SearchLimits {
+            wtime: Some(60000),
+            btime: Some(1000),
+            movestogo: Some(10),
+            ..SearchLimits::default()
+        };
+        assert_eq!(budget(limits, PieceColor::Light, 0), (6000, 24000));
+        // Without movestogo the clock has to last for 40 moves
+        let limits = SearchLimits {
+            wtime: Some(60000),
+            ..SearchLimits::default()
+        };
+        assert_eq!(budget(limits, PieceColor::Light, 0), (1500, 6000));
+    }
+
+    #[test]
+    fn increment_adds_to_the_optimum() {
+        let limits = SearchLimits {
+            wtime: Some(1000),
+            btime: Some(60000),
+            winc: 5000,
+            binc: 2000,
+            ..SearchLimits::default()
+        };
+        assert_eq!(budget(limits.clone(), PieceColor::Dark, 0), (3000, 12000));
+        // Never more than three quarters of what is left, however large the increment
+        assert_eq!(budget(limits, PieceColor::Light, 0), (750, 800));
+    }
+
+    #[test]
+    fn overhead_never_leaves_an_empty_budget() {
+        let limits = SearchLimits {
+            wtime: Some(10),
+            ..SearchLimits::default()
+        };
+        assert_eq!(budget(limits, PieceColor::Light, 30), (1, 1));
+        let limits = SearchLimits {
+            movetime: Some(5),
+            ..SearchLimits::default()
+        };
+        assert_eq!(budget(limits, PieceColor::Light, 30), (1, 1));
+    }
+
+    #[test]
+    fn untimed_searches_have_no_budget() {
+        let infinite = SearchLimits {
+            wtime: Some(60000),
+            infinite: true,
+            ..SearchLimits::default()
+        };
+        assert!(TimeManager::new(&infinite, PieceColor::Light, 30, 0).is_none());
+        let other_side = SearchLimits {
+            btime: Some(60000),
+            ..SearchLimits::default()
+        };
+        assert!(TimeManager::new(&other_side, PieceColor::Light, 30, 0).is_none());
+    }
+
+    #[test]
+    fn stable_best_move_takes_less_time() {
+        let mut progress = SearchProgress::default();
+        let mut scales = vec![];
+        for _ in 0..6 {
+            progress.update(42, 25, 10);
+            scales.push(progress.time_scale());
+        }
+        // The first update finds a new best move
+        assert_eq!(scales, [130, 115, 100, 90, 80, 80]);
+        progress.update(43, 25, 10);
+        assert_eq!(progress.time_scale(), 130);
+    }
+
+    #[test]
+    fn falling_score_takes_more_time() {
+        let mut progress = SearchProgress::default();
+        progress.update(42, 100, 10);
+        progress.update(42, 50, 10);
+        assert_eq!(progress.time_scale(), 115 * 150 / 100);
+        // Capped once the score has dropped a pawn
+        progress.update(42, -300, 10);
+        assert_eq!(progress.time_scale(), 100 * 2);
+        // A rising score is no reason to hurry
+        progress.update(42, 500, 10);
+        assert_eq!(progress.time_scale(), 90);
+    }
+
+    #[test]
+    fn next_iteration_has_to_fit_the_scaled_optimum() {
+        let time = TimeManager {
+            start_time: uci::get_curr_time(),
+            optimum: 10000,
+            maximum: 40000,
+        };
+        let mut progress = SearchProgress::default();
+        for _ in 0..5 {
+            progress.update(42, 0, 4500);
+        }
+        // Steady: 80% of the optimum, too little for an iteration twice as long as the last
+        assert!(!time.should_start_iteration(&progress));
+        // A new best move stretches the target to 130%
+        progress.update(43, 0, 4500);
+        assert!(time.should_start_iteration(&progress));
+    }
+}
@@ -1,6 +1,10 @@
 use chess::board::Board;
+use chess::moves::Move;
 use crate::search;
 
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+
 #[derive(Copy, Clone, Default, PartialEq)]
 pub enum TTFlag {
     #[default]
@@ -9,102 +13,238 @@ pub enum TTFlag {
     Beta,
 }
 
+impl TTFlag {
+    fn from_bits(bits: u64) -> Self {
+        match bits {
+            1 => Self::Alpha,
+            2 => Self::Beta,
+            _ => Self::Exact,
+        }
+    }
+}
+
+/// Decoded contents of a table slot
 #[derive(Copy, Clone, Default)]
 pub struct TT {
-    // Position identifiers
-    pub key: u64,
-    pub lock: u64,
     // Search score of the current position
     pub score: i32,
     // Current search depth
     pub depth: u32,
     // (Fail-low, fail-high, PV)
     pub flag: TTFlag,
+    // Best move found, or the move that caused the cutoff; 0 when every move failed low
+    pub best_move: Move,
+    // Search that stored the entry
+    generation: u8,
+}
+
+// Layout of the packed data word. Moves only use their low 24 bits and the score
+// sits at the top so that it is sign extended when shifted back down.
+const MOVE_BITS: u64 = 0xff_ffff;
+const DEPTH_SHIFT: u64 = 24;
+const DEPTH_BITS: u64 = 0xff;
+const FLAG_SHIFT: u64 = 32;
+const FLAG_BITS: u64 = 0x3;
+const GEN_SHIFT: u64 = 34;
+const GEN_BITS: u64 = 0x3f;
+const SCORE_SHIFT: u64 = 40;
+
+impl TT {
+    fn pack(&self) -> u64 {
+        (self.best_move as u64 & MOVE_BITS)
+            | ((self.depth.min(DEPTH_BITS as u32) as u64) << DEPTH_SHIFT)
+            | ((self.flag as u64) << FLAG_SHIFT)
+            | ((self.generation as u64 & GEN_BITS) << GEN_SHIFT)
+            | ((self.score as i64 as u64) << SCORE_SHIFT)
+    }
+
+    fn unpack(data: u64) -> Self {
+        Self {
+            score: ((data as i64) >> SCORE_SHIFT) as i32,
+            depth: ((data >> DEPTH_SHIFT) & DEPTH_BITS) as u32,
+            flag: TTFlag::from_bits((data >> FLAG_SHIFT) & FLAG_BITS),
+            best_move: (data & MOVE_BITS) as Move,
+            generation: ((data >> GEN_SHIFT) & GEN_BITS) as u8,
+        }
+    }
+
+    /// The score to return without searching, if the entry is deep enough and its
+    /// bound settles the window
+    pub fn cutoff(&self, alpha: i32, beta: i32, depth: u32) -> Option<i32> {
+        // Check if depth is the same
+        if self.depth < depth {
+            return None;
+        }
+        match self.flag {
+            // Match EXACT (PV node) score
+            TTFlag::Exact => Some(self.score),
+            // Match ALPHA (fail-low node) score
+            TTFlag::Alpha if self.score <= alpha => Some(alpha),
+            // Match BETA (fail-high node) score
+            TTFlag::Beta if self.score >= beta => Some(beta),
+            _ => None,
+        }
+    }
+}
+
+// A slot is two independent words: the packed entry and the position lock XORed with it.
+// Threads read and write slots without any locking, so a slot can end up with the halves
+// of two different stores; those (and any other lock mismatch) simply fail verification.
+#[derive(Default)]
+struct Slot {
+    check: AtomicU64,
+    data: AtomicU64,
+}
+
+impl Slot {
+    fn load(&self, lock: u64) -> Option<TT> {
+        let check = self.check.load(Ordering::Relaxed);
+        let data = self.data.load(Ordering::Relaxed);
+        (check ^ data == lock).then(|| TT::unpack(data))
+    }
+
+    fn store(&self, lock: u64, entry: TT) {
+        let data = entry.pack();
+        self.data.store(data, Ordering::Relaxed);
+        self.check.store(lock ^ data, Ordering::Relaxed);
+    }
+
+    // How much the entry is worth keeping: its depth, less for every search since it was stored.
+    // Read without verification, as a torn entry can only make a poor replacement choice.
+    fn worth(&self, generation: u8) -> i32 {
+        let entry = TT::unpack(self.data.load(Ordering::Relaxed));
+        let age = (generation.wrapping_sub(entry.generation) as u64 & GEN_BITS) as i32;
+        entry.depth as i32 - 8 * age
+    }
 }
 
-const HASH_MB: usize = 256;
-const HASH_SIZE: usize = 0x100000 * HASH_MB;
-const HASH_ENTRIES: usize = HASH_SIZE / std::mem::size_of::<TT>();
+// Positions that map to the same index share a bucket of one cache line. All but the last
+// slot are depth-preferred, the last one always takes whatever they turn down.
+const BUCKET_SIZE: usize = 4;
 
-#[derive(Clone)]
+#[derive(Default)]
+#[repr(align(64))]
+struct Bucket {
+    slots: [Slot; BUCKET_SIZE],
+}
+
+// Size limits of the table in megabytes, as advertised by the 'Hash' UCI option
+pub const DEFAULT_HASH_MB: usize = 256;
+pub const MIN_HASH_MB: usize = 1;
+pub const MAX_HASH_MB: usize = 1024;
+
+/// Transposition table shared by every search thread. All operations take `&self`;
+/// changing the size means building a new table.
 pub struct HashTT {
-    table: Vec<TT>,
+    table: OnceLock<Box<[Bucket]>>,
+    // Requested size; the table itself is only allocated by `new_search()`
+    size_mb: usize,
+    // Bumped for every search so that entries from earlier ones can be told apart
+    generation: AtomicU8,
 }
 
-fn get_tt_ind(key: u64) -> usize {
-    key as usize % HASH_ENTRIES
+impl Default for HashTT {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASH_MB)
+    }
 }
 
 impl HashTT {
-    pub fn new() -> Self {
-        let mut this = Self {
-            table: vec![],
-        };
-        // Initialize the vec with all the values
-        for _ in 0..HASH_ENTRIES {
-            this.table.push(TT::default());
+    /// The memory isn't allocated until `new_search()`, so an engine that is configured
+    /// with a smaller table after startup never holds the default size
+    pub fn new(size_mb: usize) -> Self {
+        Self {
+            table: OnceLock::new(),
+            size_mb: size_mb.clamp(MIN_HASH_MB, MAX_HASH_MB),
+            generation: AtomicU8::new(0),
         }
-        this
     }
 
-    pub fn clear_table(&mut self) {
-        // self.table.clear();
-        for el in self.table.iter_mut() {
-            *el = TT::default();
-        }
+    /// Called before every search: makes sure the table is allocated at its configured
+    /// size and ages every entry stored so far by one generation
+    pub fn new_search(&self) {
+        self.buckets();
+        let generation = self.generation.load(Ordering::Relaxed);
+        self.generation
+            .store((generation + 1) & GEN_BITS as u8, Ordering::Relaxed);
     }
 
-    pub fn read_entry(
-        &mut self,
-        board: &Board,
-        alpha: i32,
-        beta: i32,
-        depth: u32,
-        ply: u32,
-    ) -> Option<i32> {
-        let entry = self.table.get_mut(get_tt_ind(board.state.key));
-        if entry.is_none() {
-            return None;
-        }
-        let entry = entry.unwrap();
-        if entry.key == board.state.key && entry.lock == board.state.lock {
-            // Check if depth is the same
-            if entry.depth >= depth {
-                // Exact score from hash entry
-                // Or extract mate distance from actual position
-                if entry.score < -search::MATE_SCORE {
-                    entry.score += ply as i32;
-                }
-                if entry.score > search::MATE_SCORE {
-                    entry.score -= ply as i32;
-                }
+    fn buckets(&self) -> &[Bucket] {
+        self.table.get_or_init(|| {
+            let buckets = (self.size_mb << 20) / std::mem::size_of::<Bucket>();
+            (0..buckets).map(|_| Bucket::default()).collect()
+        })
+    }
 
-                // Match EXACT (PV node) score
-                if entry.flag == TTFlag::Exact {
-                    return Some(entry.score);
-                }
-                // Match ALPHA (fail-low node) score
-                if (entry.flag == TTFlag::Alpha) && (entry.score <= alpha) {
-                    return Some(alpha);
-                }
-                // Match BETA (fail-high node) score
-                if (entry.flag == TTFlag::Beta) && (entry.score >= beta) {
-                    return Some(beta);
-                }
+    pub fn size_mb(&self) -> usize {
+        self.size_mb
+    }
+
+    /// Permille of the table taken up by the current search, sampled from its first
+    /// thousand slots
+    pub fn hashfull(&self) -> u32 {
+        let Some(table) = self.table.get() else {
+            return 0;
+        };
+        let generation = self.generation.load(Ordering::Relaxed);
+        let sample: Vec<u64> = table
+            .iter()
+            .flat_map(|bucket| &bucket.slots)
+            .take(1000)
+            .map(|slot| slot.data.load(Ordering::Relaxed))
+            .collect();
+        let used = sample
+            .iter()
+            .filter(|&&data| data != 0 && TT::unpack(data).generation == generation)
+            .count();
+        (used * 1000 / sample.len().max(1)) as u32
+    }
+
+    pub fn clear_table(&self) {
+        if let Some(table) = self.table.get() {
+            for slot in table.iter().flat_map(|bucket| &bucket.slots) {
+                slot.check.store(0, Ordering::Relaxed);
+                slot.data.store(0, Ordering::Relaxed);
             }
         }
-        None
+    }
+
+    // Maps the key onto [0, table size) by multiplication, so the table doesn't
+    // need a power of two number of buckets
+    fn get_bucket(&self, key: u64) -> &Bucket {
+        let buckets = self.buckets();
+        &buckets[((key as u128 * buckets.len() as u128) >> 64) as usize]
+    }
+
+    fn load(&self, board: &Board) -> Option<TT> {
+        self.get_bucket(board.state.key)
+            .slots
+            .iter()
+            .find_map(|slot| slot.load(board.state.lock))
+    }
+
+    /// Looks up the current position. Mate scores come back relative to `ply`.
+    pub fn probe(&self, board: &Board, ply: u32) -> Option<TT> {
+        let mut entry = self.load(board)?;
+        // Extract mate distance from actual position
+        if entry.score < -search::MATE_SCORE {
+            entry.score += ply as i32;
+        }
+        if entry.score > search::MATE_SCORE {
+            entry.score -= ply as i32;
+        }
+        Some(entry)
     }
 
     pub fn write_entry(
-        &mut self,
+        &self,
         board: &Board,
         depth: u32,
         mut score: i32,
         flag: TTFlag,
+        mut best_move: Move,
         ply: u32,
     ) {
-        let ind = get_tt_ind(board.state.key);
         // Store mate score independent from the actual path
         if score < -search::MATE_SCORE {
             score -= ply as i32;
@@ -112,12 +252,245 @@ impl HashTT {
         if score > search::MATE_SCORE {
             score += ply as i32;
         }
+        let lock = board.state.lock;
+        let generation = self.generation.load(Ordering::Relaxed);
+        let bucket = self.get_bucket(board.state.key);
+
+        // A position that is already stored is updated in place
+        let existing = bucket
+            .slots
+            .iter()
+            .find_map(|slot| slot.load(lock).map(|old| (slot, old)));
+        let slot = match existing {
+            // A bound from a shallower search (quiescence or a reduced depth) doesn't replace
+            // a deeper result of this search; only its move is worth keeping
+            Some((slot, old))
+                if old.generation == generation && old.depth > depth && flag != TTFlag::Exact =>
+            {
+                if best_move != 0 && best_move != old.best_move {
+                    slot.store(lock, TT { best_move, ..old });
+                }
+                return;
+            }
+            Some((slot, old)) => {
+                // A fail-low has no best move of its own, so keep the one from an earlier search
+                if best_move == 0 {
+                    best_move = old.best_move;
+                }
+                slot
+            }
+            None => {
+                // The least valuable depth-preferred slot gives way to an entry searched at
+                // least as deep or if it was left over from an earlier search
+                let (preferred, always) = bucket.slots.split_at(BUCKET_SIZE - 1);
+                let victim = preferred
+                    .iter()
+                    .min_by_key(|slot| slot.worth(generation))
+                    .unwrap();
+                if victim.worth(generation) <= depth as i32 {
+                    victim
+                } else {
+                    &always[0]
+                }
+            }
+        };
 
         // Write entry into hash table
-        self.table[ind].key = board.state.key;
-        self.table[ind].lock = board.state.lock;
-        self.table[ind].score = score;
-        self.table[ind].depth = depth;
-        self.table[ind].flag = flag
+        slot.store(
+            lock,
+            TT {
+                score,
+                depth,
+                flag,
+                best_move,
+                generation,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(score: i32, depth: u32, flag: TTFlag, best_move: Move) -> TT {
+        TT {
+            score,
+            depth,
+            flag,
+            best_move,
+            generation: 37,
+        }
+    }
+
+    #[test]
+    fn pack_round_trips_every_field() {
+        let mate = search::MATE_SCORE + 500;
+        let entries = [
+            entry(-12345, 7, TTFlag::Alpha, 0x12_3456),
+            entry(mate, 1, TTFlag::Exact, 0xff_ffff),
+            entry(-mate, 0, TTFlag::Beta, 1),
+            entry(0, 255, TTFlag::Exact, 0),
+        ];
+        for original in entries {
+            let unpacked = TT::unpack(original.pack());
+            assert_eq!(unpacked.score, original.score);
+            assert_eq!(unpacked.depth, original.depth);
+            assert!(unpacked.flag == original.flag);
+            assert_eq!(unpacked.best_move, original.best_move);
+            assert_eq!(unpacked.generation, original.generation);
+        }
+    }
+
+    #[test]
+    fn pack_clamps_depth() {
+        let unpacked = TT::unpack(entry(-1, 300, TTFlag::Beta, 0xff_ffff).pack());
+        assert_eq!(unpacked.depth, 255);
+        // The clamped depth doesn't spill into the neighbouring fields
+        assert_eq!(unpacked.score, -1);
+        assert!(unpacked.flag == TTFlag::Beta);
+        assert_eq!(unpacked.best_move, 0xff_ffff);
+        assert_eq!(unpacked.generation, 37);
+    }
+
+    #[test]
+    fn slot_only_verifies_its_own_lock() {
+        let slot = Slot::default();
+        slot.store(0x1234_5678_9abc_def0, entry(50, 4, TTFlag::Exact, 99));
+        assert_eq!(slot.load(0x1234_5678_9abc_def0).unwrap().score, 50);
+        assert!(slot.load(0x1234_5678_9abc_def1).is_none());
+    }
+
+    #[test]
+    fn torn_slot_fails_verification() {
+        let (first, second) = (Slot::default(), Slot::default());
+        first.store(11, entry(50, 4, TTFlag::Exact, 99));
+        second.store(22, entry(-70, 9, TTFlag::Alpha, 0));
+        // The check word of one store next to the data word of the other
+        let torn = Slot {
+            check: AtomicU64::new(first.check.load(Ordering::Relaxed)),
+            data: AtomicU64::new(second.data.load(Ordering::Relaxed)),
+        };
+        assert!(torn.load(11).is_none());
+        assert!(torn.load(22).is_none());
+    }
+
+    // Positions with small keys all land in the first bucket
+    fn position(key: u64) -> Board {
+        let mut board = Board::new();
+        board.state.key = key;
+        board.state.lock = key * 1000;
+        board
+    }
+
+    fn new_table() -> HashTT {
+        let tt = HashTT::new(MIN_HASH_MB);
+        tt.new_search();
+        tt
+    }
+
+    fn stored_depth(tt: &HashTT, key: u64) -> Option<u32> {
+        tt.probe(&position(key), 0).map(|entry| entry.depth)
+    }
+
+    #[test]
+    fn stored_position_is_updated_in_place() {
+        let tt = new_table();
+        tt.write_entry(&position(1), 8, 10, TTFlag::Beta, 5, 0);
+        tt.write_entry(&position(1), 2, 20, TTFlag::Exact, 6, 0);
+        let entry = tt.probe(&position(1), 0).unwrap();
+        assert_eq!((entry.depth, entry.score, entry.best_move), (2, 20, 6));
+        let copies = tt
+            .get_bucket(1)
+            .slots
+            .iter()
+            .filter(|slot| slot.load(1000).is_some())
+            .count();
+        assert_eq!(copies, 1);
+    }
+
+    #[test]
+    fn shallower_bound_keeps_a_deeper_entry() {
+        let tt = new_table();
+        tt.write_entry(&position(1), 8, 10, TTFlag::Exact, 5, 0);
+        tt.write_entry(&position(1), 2, 20, TTFlag::Beta, 6, 0);
+        let entry = tt.probe(&position(1), 0).unwrap();
+        assert!(entry.flag == TTFlag::Exact);
+        // Only the move is taken over
+        assert_eq!((entry.depth, entry.score, entry.best_move), (8, 10, 6));
+        tt.write_entry(&position(1), 3, -40, TTFlag::Alpha, 0, 0);
+        let entry = tt.probe(&position(1), 0).unwrap();
+        assert_eq!((entry.depth, entry.score, entry.best_move), (8, 10, 6));
+
+        // The deeper entry of an earlier search gives way
+        tt.new_search();
+        tt.write_entry(&position(1), 2, 20, TTFlag::Beta, 7, 0);
+        let entry = tt.probe(&position(1), 0).unwrap();
+        assert!(entry.flag == TTFlag::Beta);
+        assert_eq!((entry.depth, entry.score, entry.best_move), (2, 20, 7));
+    }
+
+    #[test]
+    fn fail_low_keeps_the_earlier_best_move() {
+        let tt = new_table();
+        tt.write_entry(&position(1), 4, 30, TTFlag::Exact, 77, 0);
+        tt.write_entry(&position(1), 5, -10, TTFlag::Alpha, 0, 0);
+        let entry = tt.probe(&position(1), 0).unwrap();
+        assert!(entry.flag == TTFlag::Alpha);
+        assert_eq!(entry.best_move, 77);
+    }
+
+    #[test]
+    fn shallower_entry_goes_to_the_always_replace_slot() {
+        let tt = new_table();
+        for key in 1..=3 {
+            tt.write_entry(&position(key), 10, 0, TTFlag::Exact, 0, 0);
+        }
+        tt.write_entry(&position(4), 2, 0, TTFlag::Exact, 0, 0);
+        assert_eq!(stored_depth(&tt, 4), Some(2));
+        // The next shallow entry takes the always-replace slot over
+        tt.write_entry(&position(5), 1, 0, TTFlag::Exact, 0, 0);
+        assert_eq!(stored_depth(&tt, 4), None);
+        assert_eq!(stored_depth(&tt, 5), Some(1));
+        for key in 1..=3 {
+            assert_eq!(stored_depth(&tt, key), Some(10));
+        }
+    }
+
+    #[test]
+    fn older_generations_are_evicted_first() {
+        let tt = new_table();
+        for key in 1..=3 {
+            tt.write_entry(&position(key), 10, 0, TTFlag::Exact, 0, 0);
+        }
+        tt.new_search();
+        // Searched again, so it counts as current
+        tt.write_entry(&position(1), 10, 0, TTFlag::Exact, 0, 0);
+        tt.write_entry(&position(4), 3, 0, TTFlag::Exact, 0, 0);
+        assert_eq!(stored_depth(&tt, 1), Some(10));
+        assert_eq!(stored_depth(&tt, 2), None);
+        assert_eq!(stored_depth(&tt, 3), Some(10));
+        assert_eq!(stored_depth(&tt, 4), Some(3));
+    }
+
+    #[test]
+    fn aging_carries_over_the_generation_wrap() {
+        let tt = new_table();
+        while tt.generation.load(Ordering::Relaxed) != GEN_BITS as u8 {
+            tt.new_search();
+        }
+        for key in 1..=3 {
+            tt.write_entry(&position(key), 10, 0, TTFlag::Exact, 0, 0);
+        }
+        tt.new_search();
+        assert_eq!(tt.generation.load(Ordering::Relaxed), 0);
+        // One search old, not 63 searches in the future
+        tt.write_entry(&position(4), 3, 0, TTFlag::Exact, 0, 0);
+        assert_eq!(stored_depth(&tt, 1), None);
+        assert_eq!(stored_depth(&tt, 4), Some(3));
+        // A shallower entry still doesn't push out the ones aged by a single search
+        tt.write_entry(&position(5), 1, 0, TTFlag::Exact, 0, 0);
+        assert_eq!(stored_depth(&tt, 2), Some(10));
+        assert_eq!(stored_depth(&tt, 3), Some(10));
     }
 }
@@ -1,27 +1,91 @@
+use chess::board::Board;
 use chess::consts::{Piece, PieceColor, Sq};
 use crate::engine::Engine;
 use crate::eval;
+use crate::options::{self, Options};
 use chess::fen::FEN_POSITIONS;
 use chess::move_gen::{self, MoveList};
-use chess::moves::{self, Move};
+use chess::moves::Move;
 use crate::perft;
-use crate::search::{self, MAX_SEARCH_PLY};
+use crate::search::MAX_SEARCH_PLY;
 use crate::threads;
+use crate::timeman::{SearchProgress, TimeManager};
 use crate::engine::VERSION;
 
 use std::time::{Duration, SystemTime, SystemTimeError};
 
+/// Everything a 'go' command can ask for. Times are in milliseconds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchLimits {
+    pub wtime: Option<u32>,
+    pub btime: Option<u32>,
+    pub winc: u32,
+    pub binc: u32,
+    pub movestogo: Option<u32>,
+    pub movetime: Option<u32>,
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    // Stop once a mate in this many moves is found
+    pub mate: Option<u32>,
+    pub infinite: bool,
+    pub ponder: bool,
+    // Root moves the search is restricted to; empty means every legal move
+    pub searchmoves: Vec<Move>,
+}
+
+impl SearchLimits {
+    // Example UCI command
+    // go wtime 180000 btime 180000 winc 1000 binc 1000 movestogo 40 searchmoves e2e4 d2d4
+    /// Parses the arguments of 'go'. Unknown tokens and values that aren't numbers are
+    /// skipped, and the move list of 'searchmoves' ends at the first illegal move.
+    pub fn parse(board: &Board, args: &str) -> Self {
+        fn next_num<T: std::str::FromStr>(tokens: &mut dyn Iterator<Item = &str>) -> Option<T> {
+            tokens.next()?.parse().ok()
+        }
+
+        let mut limits = Self::default();
+        let mut tokens = args.split_whitespace().peekable();
+        while let Some(token) = tokens.next() {
+            match token {
+                "wtime" => limits.wtime = next_num(&mut tokens),
+                "btime" => limits.btime = next_num(&mut tokens),
+                "winc" => limits.winc = next_num(&mut tokens).unwrap_or(0),
+                "binc" => limits.binc = next_num(&mut tokens).unwrap_or(0),
+                "movestogo" => limits.movestogo = next_num(&mut tokens),
+                "movetime" => limits.movetime = next_num(&mut tokens),
+                "depth" => limits.depth = next_num(&mut tokens),
+                "nodes" => limits.nodes = next_num(&mut tokens),
+                "mate" => limits.mate = next_num(&mut tokens),
+                "infinite" => limits.infinite = true,
+                "ponder" => limits.ponder = true,
+                "searchmoves" => {
+                    while let Some(mv) = tokens.peek().and_then(|mv_str| find_move(board, mv_str)) {
+                        limits.searchmoves.push(mv);
+                        tokens.next();
+                    }
+                }
+                _ => {}
+            }
+        }
+        limits
+    }
+
+    // Clock time and increment of the side to move
+    pub fn clock(&self, side: PieceColor) -> (Option<u32>, u32) {
+        if side == PieceColor::Light {
+            (self.wtime, self.winc)
+        } else {
+            (self.btime, self.binc)
+        }
+    }
+}
+
 pub struct UCIState {
     pub stop: bool,
-    pub time_controlled: bool,
-    pub depth: u32,
-
-    time_left: Option<u32>,
-    increment: u32,
-    moves_to_go: u32,
-    move_time: Option<u32>,
-    start_time: u128,
-    stop_time: u128,
+    // Searching on the opponent's time; the clock only starts with 'ponderhit'
+    pub pondering: bool,
+    // Budget of the current search, if it's timed at all
+    pub time: Option<TimeManager>,
 }
 
 pub fn get_curr_time() -> u128 {
@@ -36,38 +100,41 @@ pub fn get_curr_time() -> u128 {
     since_the_epoch.as_millis()
 }
 
+impl Default for UCIState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl UCIState {
     pub fn new() -> Self {
         Self {
             stop: false,
-            time_controlled: false,
-
-            time_left: None,
-            increment: 0,
-            moves_to_go: 40,
-            move_time: None,
-            start_time: 0,
-            stop_time: 0,
-            depth: 0,
+            pondering: false,
+            time: None,
         }
     }
 
     pub fn check_up(&mut self) {
-        if self.time_controlled && get_curr_time() >= self.stop_time {
+        if !self.pondering && self.time.as_ref().is_some_and(TimeManager::out_of_time) {
             self.stop = true;
         }
     }
 
-    fn reset_time_control(&mut self) {
-        self.moves_to_go = 40;
-        self.time_left = None;
-        self.move_time = None;
-        self.increment = 0;
-        self.start_time = 0;
-        self.stop_time = 0;
+    // Whether the main worker may begin another iteration as far as the clock is concerned
+    pub fn should_start_iteration(&self, progress: &SearchProgress) -> bool {
+        self.pondering
+            || self
+                .time
+                .as_ref()
+                .is_none_or(|time| time.should_start_iteration(progress))
+    }
 
-        self.time_controlled = false;
-        self.stop = false;
+    /// The opponent played the expected move, so the ponder search carries on as a
+    /// normal one. Its budget counts from the 'go ponder', so the time spent pondering
+    /// is taken off what is left.
+    pub fn ponderhit(&mut self) {
+        self.pondering = false;
     }
 }
 
@@ -101,23 +168,28 @@ pub fn parse(engine: &mut Engine, input_str: &str, should_quit: &mut bool) {
                 println!("Search thread has joined the input thread.");
             }
         }
-        "ucinewgame" => parse_position(engine, "startpos"),
-        "uci" => print_author_info(),
+        "ucinewgame" => new_game(engine),
+        "uci" => print_author_info(&engine.options),
         "isready" => println!("readyok"),
-        "position" => parse_position(engine, &rest),
-        "go" => parse_go(engine, &rest),
+        "position" => parse_position(engine, rest),
+        "go" => parse_go(engine, rest),
+        "ponderhit" => {
+            if let Ok(mut engine_state) = engine.uci_state.write() {
+                engine_state.ponderhit();
+            }
+        }
+        "setoption" => parse_setoption(engine, rest),
         "evalpos" => {
             let eval = eval::evaluate(
                 &engine.board.pos,
                 engine.board.state.side,
-                &engine.attack_info,
                 &engine.eval_mask,
             );
             println!("Current eval: {eval}");
         }
         "genmoves" => {
             let mut ml = MoveList::new();
-            move_gen::generate(&engine.board, &engine.attack_info, &mut ml);
+            move_gen::generate_legal(&engine.board, &mut ml);
             ml.print();
         }
         "display" | "d" => engine.board.display(),
@@ -130,35 +202,40 @@ pub fn parse(engine: &mut Engine, input_str: &str, should_quit: &mut bool) {
             "off" => engine.debug = false,
             _ => {}
         },
-        _ => {}
+        "" => {}
+        unknown => println!("info string unknown command '{}'", unknown),
     }
 }
 
+// Entries from another game are of no use; within a game the table is only aged between searches
+fn new_game(engine: &mut Engine) {
+    parse_position(engine, "startpos");
+    engine.search_info.tt.clear_table();
+}
+
 fn parse_position(engine: &mut Engine, args: &str) {
     let ind = split_by_first_space(args);
     let first_arg = &args[0..ind];
     let rest = &args[ind..].trim();
 
-    match first_arg {
-        "startpos" => {
-            engine.board.set_fen(FEN_POSITIONS[1]);
-        }
-        "fen" => {
-            engine.board.set_fen(&rest.trim());
-        }
+    let fen = match first_arg {
+        "startpos" => FEN_POSITIONS[1],
+        // Everything up to the move list is part of the FEN
+        "fen" => rest.find("moves").map_or(*rest, |i| &rest[..i]).trim(),
         // TODO: figure out the best course of action here
         // Is it better to ignore an unknown argument or display a message stating it?
         // Good for human use, not so good when interacting with a GUI program use
-        _ => {}
+        _ => return,
+    };
+    if let Err(e) = engine.board.set_fen(fen) {
+        println!("info string invalid FEN '{}': {}", fen, e);
+        return;
     }
+    engine.undo_stack.clear();
 
     if let Some(i) = rest.find("moves") {
         parse_moves(engine, &rest[i..]);
     }
-    // Clear the transposition table to setup for the new position
-    if let Ok(mut engine_tt) = engine.search_info.tt.write() {
-        engine_tt.clear_table();
-    }
 }
 
 fn parse_moves(engine: &mut Engine, args: &str) {
@@ -166,46 +243,34 @@ fn parse_moves(engine: &mut Engine, args: &str) {
     let rest = &args[ind..].trim();
     let list_of_moves = rest.split(' ');
 
-    for el in list_of_moves {
-        // If current move is a promotion
-        // If white is promoting - if target square has an '8', it means the pawn is being promoted on the 8th rank
-        let mv_str = if el.trim().len() == 5 && el.chars().nth(3).unwrap() == '8' {
-            format!(
-                "{}{}",
-                &el[0..4],
-                el.chars().nth(4).unwrap().to_ascii_uppercase()
-            )
-        } else {
-            el.to_string()
-        };
-        if let Some(mv) = find_move(engine, &mv_str) {
-            moves::play(
-                &mut engine.board,
-                &engine.attack_info,
-                mv,
-                moves::MoveFlag::AllMoves,
-            );
+    for mv_str in list_of_moves {
+        if let Some(mv) = find_move(&engine.board, mv_str) {
+            let undo = engine.board.make_move_unchecked(mv);
+            engine.undo_stack.push(undo);
         } else {
             eprintln!("Received '{mv_str}'. Unknown move.");
         }
     }
 }
 
-fn find_move(engine: &Engine, move_str: &str) -> Option<Move> {
-    if move_str.len() != 4 && move_str.len() != 5 {
-        println!("Got {} expected move string with length 4 or 5", move_str);
+// Looks up a legal move from its coordinate notation (e.g. 'e2e4', 'e7e8q')
+fn find_move(board: &Board, move_str: &str) -> Option<Move> {
+    if (move_str.len() != 4 && move_str.len() != 5) || !move_str.is_ascii() {
         return None;
     }
-    let mut ml = move_gen::MoveList::new();
-    move_gen::generate(&engine.board, &engine.attack_info, &mut ml);
-    let source = &move_str[0..2];
-    let target = &move_str[2..4];
-    let promoted = if move_str.len() == 5 {
-        Piece::from_char(move_str.chars().nth(4).unwrap())
-    } else {
-        None
+    let source = Sq::parse(&move_str[0..2])?;
+    let target = Sq::parse(&move_str[2..4])?;
+    // The promotion piece is always lowercase, but the piece itself has the mover's color
+    let promoted = match move_str.chars().nth(4) {
+        Some(c) if board.state.side == PieceColor::Light => {
+            Some(Piece::from_char(c.to_ascii_uppercase())?)
+        }
+        Some(c) => Some(Piece::from_char(c.to_ascii_lowercase())?),
+        None => None,
     };
-    ml.search(Sq::from_str(source), Sq::from_str(target), promoted)
+    let mut ml = move_gen::MoveList::new();
+    move_gen::generate_legal(board, &mut ml);
+    ml.search(source, target, promoted)
 }
 
 fn parse_go(engine: &mut Engine, args: &str) {
@@ -213,95 +278,77 @@ fn parse_go(engine: &mut Engine, args: &str) {
 
     if &args[0..first_ind] == "perft" {
         let depth_str = &args[first_ind..].trim();
-        let perft_depth: usize = if let Ok(val) = depth_str.parse() {
-            val
-        } else {
-            10
-        };
+        let perft_depth: usize = depth_str.parse().unwrap_or(10);
         perft::test(
             &mut engine.board,
-            &engine.attack_info,
             perft_depth,
         );
         return;
     }
-    handle_time(engine, args);
-    let depth = parse_param(args, "depth").unwrap_or(search::MAX_SEARCH_PLY as u32);
-
+    // Example UCI command with time
+    // go depth 12 wtime 180000 btime 180000 binc 1000 winc 1000 movestogo 40
+    // go movetime 1000
+    let limits = SearchLimits::parse(&engine.board, args);
+    // Allocating the table can take a while, which shouldn't come out of the time budget
+    engine.search_info.tt.new_search();
     if let Ok(mut state) = engine.uci_state.write() {
-        state.depth = depth;
-        if state.move_time.is_some() {
-            state.time_left = state.move_time;
-            state.moves_to_go = 1;
-        }
-
-        // Example UCI command with time
-        // go depth 12 wtime 180000 btime 180000 binc 1000 winc 1000 movestogo 40
-        // go movetime 1000
-        state.start_time = get_curr_time();
-        if let Some(tl) = state.time_left {
-            state.time_controlled = true;
-            let mut per_move_tl = tl / state.moves_to_go;
-            if per_move_tl > 1500 {
-                per_move_tl -= 50;
-            }
-            state.time_left = Some(per_move_tl);
-            state.stop_time = state.start_time + (per_move_tl + state.increment) as u128;
-            if per_move_tl < 1500 && state.increment != 0 && state.depth == MAX_SEARCH_PLY as u32 {
-                let a = state.increment.saturating_sub(50);
-                state.stop_time = state.start_time + a as u128;
-            }
-        }
+        state.stop = false;
+        // Infinite searches only end when the GUI says so
+        state.pondering = limits.ponder && !limits.infinite;
+        state.time = TimeManager::new(
+            &limits,
+            engine.board.state.side,
+            engine.move_overhead,
+            get_curr_time(),
+        );
 
         // Print debug info
         if engine.debug {
             println!(
-                "info string time: {}, start: {}, stop: {}, depth: {}, timecontrol: {}",
-                state.time_left.unwrap_or(0),
-                state.start_time,
-                state.stop_time,
-                depth,
-                if state.time_controlled { "yes" } else { "no" }
+                "info string threads: {}, hash: {} MB, multipv: {}",
+                engine.worker_thread_count,
+                engine.search_info.tt.size_mb(),
+                engine.search_info.multi_pv
             );
+            match &state.time {
+                Some(time) => println!(
+                    "info string optimum time: {}, maximum time: {}, depth: {}",
+                    time.optimum,
+                    time.maximum,
+                    limits.depth.unwrap_or(MAX_SEARCH_PLY as u32)
+                ),
+                None => println!(
+                    "info string untimed search, depth: {}",
+                    limits.depth.unwrap_or(MAX_SEARCH_PLY as u32)
+                ),
+            }
         }
     }
-    threads::launch_search_thread(engine, depth);
-}
-
-fn handle_time(engine: &mut Engine, cmd: &str) {
-    if let Ok(mut state) = engine.uci_state.write() {
-        state.reset_time_control();
-        if engine.board.state.side == PieceColor::Light {
-            state.time_left = parse_param(cmd, "wtime");
-            state.increment = parse_param(cmd, "winc").unwrap_or(0);
-        } else {
-            state.time_left = parse_param(cmd, "btime");
-            state.increment = parse_param(cmd, "binc").unwrap_or(0);
-        }
-        state.move_time = parse_param(cmd, "movetime");
-        state.moves_to_go = parse_param(cmd, "movestogo").unwrap_or(40);
-    }
+    threads::launch_search_thread(engine, limits);
 }
 
-fn parse_param(cmd: &str, name: &str) -> Option<u32> {
-    let mut val: Option<u32> = None;
-    if let Some(ind) = cmd.find(name) {
-        let portion = &cmd[ind..];
-        let i = split_by_first_space(portion);
-        let name_portion = (&portion[i..]).trim();
-        let ind2 = split_by_first_space(name_portion);
-        if let Ok(num) = (&name_portion[0..ind2]).parse::<u32>() {
-            val = Some(num);
-        }
+// Example UCI command with an option
+// setoption name Hash value 64
+// setoption name Clear Hash
+fn parse_setoption(engine: &mut Engine, args: &str) {
+    let Some(args) = args.strip_prefix("name ") else {
+        println!("info string setoption expects 'name <id> [value <x>]'");
+        return;
+    };
+    // Option names may contain spaces, so everything up to 'value' is the name
+    let (name, value) = match args.find(" value ") {
+        Some(i) => (args[..i].trim(), Some(args[i + 7..].trim())),
+        None => (args.trim(), None),
+    };
+    if let Err(e) = options::set_option(engine, name, value) {
+        println!("info string {}", e);
     }
-    return val;
 }
 
-pub fn print_author_info() {
+pub fn print_author_info(options: &Options) {
     println!("id name engine {}", VERSION);
     println!("id author michabay05");
-    println!("option name Hash type spin default 256 min 1 max 1024");
-    println!("option name Thread type spin default 1 min 1 max 4");
+    options.print();
     println!("uciok");
 }
 
@@ -318,6 +365,12 @@ fn print_help() {
     println!("   position fen <FEN>                    |    Set board to a custom FEN");
     println!("   position fen <FEN> moves <move1> ...  |    Set board to a custom FEN then playing the following moves");
     println!("     go depth <depth>                    |    Returns the best move after search for given amount of depth");
+    println!("     go nodes <nodes> | mate <moves>     |    Searches until the node budget is spent or a short enough mate is found");
+    println!("     go wtime <ms> btime <ms> ...        |    Searches on the clock; also winc, binc, movestogo and movetime");
+    println!("                                         |    'Move Overhead' is kept in reserve for every move");
+    println!("     go infinite | ponder                |    Searches until 'stop'; 'ponderhit' puts a ponder search on the clock");
+    println!("     go ... searchmoves <move1> ...      |    Only considers the given moves at the root");
+    println!("  setoption name <id> [value <x>]        |    Sets one of the options listed by 'uci'");
     println!("                debug [ on | off ]       |    Sends additional information when needed. Off by default");
     println!("                 stop                    |    Stops libengine from calculating further");
     println!("                 quit                    |    Exit the UCI mode\n");
@@ -329,5 +382,5 @@ fn print_help() {
 }
 
 fn split_by_first_space(input_str: &str) -> usize {
-    return input_str.find(' ').unwrap_or(input_str.len());
+    input_str.find(' ').unwrap_or(input_str.len())
 }
@@ -0,0 +1,47 @@
+use libengine::engine::Engine;
+use libengine::options::{self, OptionError, OptionValue};
+
+#[test]
+fn sets_and_validates_values() {
+    let mut engine = Engine::new();
+    assert_eq!(options::set_option(&mut engine, "Hash", Some("16")), Ok(()));
+    assert_eq!(engine.options.get("hash").unwrap().value, OptionValue::Spin(16));
+
+    assert_eq!(
+        options::set_option(&mut engine, "Hash", Some("0")),
+        Err(OptionError::InvalidValue("Hash", "0".to_string()))
+    );
+    assert_eq!(
+        options::set_option(&mut engine, "Hash", Some("lots")),
+        Err(OptionError::InvalidValue("Hash", "lots".to_string()))
+    );
+    assert_eq!(
+        options::set_option(&mut engine, "Hash", None),
+        Err(OptionError::MissingValue("Hash"))
+    );
+    // A rejected value leaves the old one in place
+    assert_eq!(engine.options.get("Hash").unwrap().value, OptionValue::Spin(16));
+}
+
+#[test]
+fn applies_values_to_the_engine() {
+    let mut engine = Engine::new();
+    assert_eq!(options::set_option(&mut engine, "threads", Some("3")), Ok(()));
+    assert_eq!(engine.worker_thread_count, 3);
+    assert_eq!(options::set_option(&mut engine, "Move Overhead", Some("100")), Ok(()));
+    assert_eq!(engine.move_overhead, 100);
+    assert_eq!(options::set_option(&mut engine, "multipv", Some("4")), Ok(()));
+    assert_eq!(engine.options.get("MultiPV").unwrap().value, OptionValue::Spin(4));
+    assert_eq!(options::set_option(&mut engine, "Clear Hash", None), Ok(()));
+    assert_eq!(options::set_option(&mut engine, "SEE Pruning", Some("false")), Ok(()));
+    assert_eq!(engine.options.get("see pruning").unwrap().value, OptionValue::Check(false));
+}
+
+#[test]
+fn rejects_unknown_options() {
+    let mut engine = Engine::new();
+    assert_eq!(
+        options::set_option(&mut engine, "Contempt", Some("10")),
+        Err(OptionError::UnknownOption("Contempt".to_string()))
+    );
+}
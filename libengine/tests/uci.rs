@@ -0,0 +1,58 @@
+use chess::board::Board;
+use chess::fen::FEN_POSITIONS;
+use chess::moves::MoveUtil;
+use libengine::uci::SearchLimits;
+
+#[test]
+fn parses_the_go_grammar() {
+    let board = Board::from_fen(FEN_POSITIONS[1]).unwrap();
+    let limits = SearchLimits::parse(
+        &board,
+        "wtime 180000 btime 170000 winc 1000 binc 2000 movestogo 40 depth 12 nodes 5000000000 mate 3",
+    );
+    assert_eq!(
+        limits,
+        SearchLimits {
+            wtime: Some(180000),
+            btime: Some(170000),
+            winc: 1000,
+            binc: 2000,
+            movestogo: Some(40),
+            depth: Some(12),
+            nodes: Some(5_000_000_000),
+            mate: Some(3),
+            ..SearchLimits::default()
+        }
+    );
+
+    let limits = SearchLimits::parse(&board, "ponder movetime 500");
+    assert!(limits.ponder && !limits.infinite);
+    assert_eq!(limits.movetime, Some(500));
+    assert!(SearchLimits::parse(&board, "infinite").infinite);
+}
+
+#[test]
+fn searchmoves_ends_at_the_first_non_move() {
+    let board = Board::from_fen(FEN_POSITIONS[1]).unwrap();
+    let limits = SearchLimits::parse(&board, "searchmoves e2e4 g1f3 nodes 1000");
+    let moves: Vec<String> = limits
+        .searchmoves
+        .iter()
+        .map(|mv| mv.to_str().trim().to_string())
+        .collect();
+    assert_eq!(moves, ["e2e4", "g1f3"]);
+    assert_eq!(limits.nodes, Some(1000));
+
+    // Illegal moves aren't root moves
+    let limits = SearchLimits::parse(&board, "searchmoves e2e5 depth 3");
+    assert!(limits.searchmoves.is_empty());
+    assert_eq!(limits.depth, Some(3));
+}
+
+#[test]
+fn promotions_take_the_side_to_move() {
+    let board = Board::from_fen("8/1P6/8/8/8/8/6p1/K1k4R b - - 0 1").unwrap();
+    let limits = SearchLimits::parse(&board, "searchmoves g2h1q g2g1n");
+    assert_eq!(limits.searchmoves.len(), 2);
+    assert!(limits.searchmoves.iter().all(|mv| mv.promoted().is_some()));
+}